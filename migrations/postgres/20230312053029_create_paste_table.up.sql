@@ -0,0 +1,7 @@
+CREATE TABLE paste (
+  id         BIGSERIAL   PRIMARY KEY,
+  key        TEXT        UNIQUE NOT NULL,
+  delete_key TEXT,
+  file_name  TEXT        NOT NULL,
+  timestamp  TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+CREATE TABLE object (
+  hash      TEXT   PRIMARY KEY,
+  size      BIGINT NOT NULL,
+  ref_count BIGINT NOT NULL
+);
+
+ALTER TABLE paste ADD COLUMN object_hash TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN encoding;
+ALTER TABLE object DROP COLUMN encoding;
@@ -0,0 +1,2 @@
+ALTER TABLE object ADD COLUMN encoding TEXT;
+ALTER TABLE paste ADD COLUMN encoding TEXT;
@@ -0,0 +1 @@
+ALTER TABLE paste ADD COLUMN secret_hash TEXT;
@@ -0,0 +1,4 @@
+CREATE TABLE deleted_paste (
+  key       TEXT        PRIMARY KEY,
+  timestamp TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
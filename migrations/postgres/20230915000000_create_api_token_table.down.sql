@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN token_name;
+DROP TABLE api_token;
@@ -0,0 +1,7 @@
+CREATE TABLE api_token (
+  name       TEXT        PRIMARY KEY,
+  token_hash TEXT        UNIQUE NOT NULL,
+  timestamp  TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE paste ADD COLUMN token_name TEXT;
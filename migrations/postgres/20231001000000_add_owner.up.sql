@@ -0,0 +1,5 @@
+ALTER TABLE api_token ADD COLUMN owner TEXT;
+UPDATE api_token SET owner = name;
+
+ALTER TABLE paste ADD COLUMN owner TEXT;
+UPDATE paste SET owner = token_name;
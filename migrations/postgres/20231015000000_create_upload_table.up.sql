@@ -0,0 +1,9 @@
+CREATE TABLE upload (
+  id         BIGSERIAL   PRIMARY KEY,
+  client_ip  TEXT        NOT NULL,
+  token_name TEXT,
+  size       BIGINT      NOT NULL,
+  timestamp  TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX upload_timestamp ON upload (timestamp);
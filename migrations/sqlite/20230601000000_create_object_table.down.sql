@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN object_hash;
+DROP TABLE object;
@@ -0,0 +1,7 @@
+CREATE TABLE object (
+  hash      TEXT    PRIMARY KEY,
+  size      INTEGER NOT NULL,
+  ref_count INTEGER NOT NULL
+);
+
+ALTER TABLE paste ADD COLUMN object_hash TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN key_id;
+ALTER TABLE object DROP COLUMN key_id;
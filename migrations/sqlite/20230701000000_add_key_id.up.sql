@@ -0,0 +1,2 @@
+ALTER TABLE object ADD COLUMN key_id TEXT;
+ALTER TABLE paste ADD COLUMN key_id TEXT;
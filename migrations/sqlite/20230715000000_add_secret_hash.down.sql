@@ -0,0 +1 @@
+ALTER TABLE paste DROP COLUMN secret_hash;
@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN tier;
+ALTER TABLE object DROP COLUMN tier;
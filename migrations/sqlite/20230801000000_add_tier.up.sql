@@ -0,0 +1,2 @@
+ALTER TABLE object ADD COLUMN tier TEXT;
+ALTER TABLE paste ADD COLUMN tier TEXT;
@@ -0,0 +1 @@
+ALTER TABLE paste DROP COLUMN delete_key_hash;
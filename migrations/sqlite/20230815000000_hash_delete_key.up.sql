@@ -0,0 +1 @@
+ALTER TABLE paste ADD COLUMN delete_key_hash TEXT;
@@ -0,0 +1,4 @@
+CREATE TABLE deleted_paste (
+  key       TEXT     PRIMARY KEY,
+  timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+);
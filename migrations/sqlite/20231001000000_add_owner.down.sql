@@ -0,0 +1,2 @@
+ALTER TABLE paste DROP COLUMN owner;
+ALTER TABLE api_token DROP COLUMN owner;
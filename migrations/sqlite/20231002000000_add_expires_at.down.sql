@@ -0,0 +1 @@
+ALTER TABLE paste DROP COLUMN expires_at;
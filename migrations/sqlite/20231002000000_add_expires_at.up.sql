@@ -0,0 +1 @@
+ALTER TABLE paste ADD COLUMN expires_at DATETIME;
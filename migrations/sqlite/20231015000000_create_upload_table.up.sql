@@ -0,0 +1,9 @@
+CREATE TABLE upload (
+  id         INTEGER  PRIMARY KEY,
+  client_ip  TEXT     NOT NULL,
+  token_name TEXT,
+  size       INTEGER  NOT NULL,
+  timestamp  DATETIME NOT NULL
+);
+
+CREATE INDEX upload_timestamp ON upload (timestamp);
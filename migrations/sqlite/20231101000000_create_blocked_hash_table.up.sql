@@ -0,0 +1,4 @@
+CREATE TABLE blocked_hash (
+  hash      TEXT     PRIMARY KEY,
+  timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+);
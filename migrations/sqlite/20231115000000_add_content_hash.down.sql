@@ -0,0 +1 @@
+ALTER TABLE paste DROP COLUMN content_hash;
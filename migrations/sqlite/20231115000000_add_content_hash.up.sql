@@ -0,0 +1,5 @@
+ALTER TABLE paste ADD COLUMN content_hash TEXT;
+
+-- objects of pastes which aren't private were named by the hash of their
+-- content until now
+UPDATE paste SET content_hash = object_hash WHERE secret_hash IS NULL;
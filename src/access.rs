@@ -0,0 +1,75 @@
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use crate::config;
+use crate::error::AppError;
+
+/// A group of routes which clients can be allowed or denied access to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteGroup {
+    Upload,
+    Download,
+    Delete,
+}
+
+impl RouteGroup {
+    /// Describe what clients in the group are doing.
+    pub fn action(&self) -> &'static str {
+        match self {
+            RouteGroup::Upload => "upload pastes",
+            RouteGroup::Download => "download pastes",
+            RouteGroup::Delete => "delete pastes",
+        }
+    }
+}
+
+/// The addresses which are allowed or denied access to each group of routes.
+///
+/// The lists are shared between clones, so that they can be replaced while the
+/// service is running.
+#[derive(Clone)]
+pub struct AccessControl {
+    lists: Arc<RwLock<config::Access>>,
+}
+
+impl AccessControl {
+    pub fn new(config: Option<&config::Access>) -> Self {
+        AccessControl {
+            lists: Arc::new(RwLock::new(config.cloned().unwrap_or_default())),
+        }
+    }
+
+    /// Replace the lists with newly loaded ones.
+    pub fn reload(&self, config: Option<&config::Access>) {
+        *self.lists.write().unwrap() = config.cloned().unwrap_or_default();
+    }
+
+    /// Check that a client is allowed to access a group of routes.
+    ///
+    /// Clients in the deny list are always blocked, and if there is an allow
+    /// list, clients not in it are blocked as well.
+    pub fn check(&self, group: RouteGroup, client_ip: IpAddr) -> crate::AppResult<()> {
+        let lists = self.lists.read().unwrap();
+        let list = match group {
+            RouteGroup::Upload => &lists.upload,
+            RouteGroup::Download => &lists.download,
+            RouteGroup::Delete => &lists.delete,
+        };
+        let Some(list) = list else { return Ok(()) };
+
+        let contains = |nets: &Option<Vec<_>>| {
+            nets.iter()
+                .flatten()
+                .any(|net: &ipnet::IpNet| net.contains(&client_ip))
+        };
+
+        let allowed = list.allow.is_none() || contains(&list.allow);
+        if !allowed || contains(&list.deny) {
+            return Err(AppError::Blocked {
+                action: group.action(),
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{self, Paste};
+
+/// The path of the paste metadata in an archive.
+pub const PASTES_PATH: &str = "pastes.json";
+
+/// The prefix of the paths of stored objects in an archive.
+pub const OBJECTS_PREFIX: &str = "objects/";
+
+/// The version of the metadata written to archives.
+///
+/// Version 1 archives hold a bare list of pastes rather than a manifest.
+pub const VERSION: u32 = 2;
+
+/// The paste metadata in an archive.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub pastes: Vec<PasteRecord>,
+}
+
+/// A paste as written to an archive.
+///
+/// This is kept apart from [`Paste`], so that changes to the database don't
+/// change the archive format by accident.
+#[derive(Serialize, Deserialize)]
+pub struct PasteRecord {
+    pub key: String,
+    /// The plaintext delete key, only found in archives exported before delete
+    /// keys were hashed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_key: Option<String>,
+    #[serde(default)]
+    pub delete_key_hash: Option<String>,
+    pub file_name: String,
+    pub timestamp: DateTime<Utc>,
+    pub object_hash: Option<String>,
+    /// Only found in archives exported since objects could be named by
+    /// something other than the hash of their content.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    pub encoding: Option<String>,
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub secret_hash: Option<String>,
+    #[serde(default)]
+    pub token_name: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl PasteRecord {
+    /// Turn the record into a paste, hashing its delete key if it was stored
+    /// in plaintext.
+    ///
+    /// The paste is put in the default storage tier.
+    pub fn into_paste(self) -> Paste {
+        let delete_key_hash = self
+            .delete_key_hash
+            .or_else(|| self.delete_key.as_deref().map(models::hash_delete_key));
+        // objects of pastes which aren't private used to be named by the hash
+        // of their content
+        let content_hash = self.content_hash.or_else(|| {
+            self.object_hash
+                .clone()
+                .filter(|_| self.secret_hash.is_none())
+        });
+
+        Paste {
+            key: self.key,
+            delete_key_hash,
+            file_name: self.file_name,
+            timestamp: self.timestamp,
+            object_hash: self.object_hash,
+            content_hash,
+            encoding: self.encoding,
+            key_id: self.key_id,
+            secret_hash: self.secret_hash,
+            tier: None,
+            token_name: self.token_name,
+            owner: self.owner,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+impl From<Paste> for PasteRecord {
+    fn from(paste: Paste) -> Self {
+        PasteRecord {
+            key: paste.key,
+            delete_key: None,
+            delete_key_hash: paste.delete_key_hash,
+            file_name: paste.file_name,
+            timestamp: paste.timestamp,
+            object_hash: paste.object_hash,
+            content_hash: paste.content_hash,
+            encoding: paste.encoding,
+            key_id: paste.key_id,
+            secret_hash: paste.secret_hash,
+            token_name: paste.token_name,
+            owner: paste.owner,
+            expires_at: paste.expires_at,
+        }
+    }
+}
+
+/// Read the paste metadata of an archive of any version.
+pub fn parse_pastes(metadata: &[u8]) -> anyhow::Result<Vec<PasteRecord>> {
+    let metadata = serde_json::from_slice::<serde_json::Value>(metadata)?;
+    if metadata.is_array() {
+        return Ok(serde_json::from_value(metadata)?);
+    }
+
+    // check the version first, since newer manifests may not parse at all
+    let version = metadata.get("version").and_then(|version| version.as_u64());
+    match version {
+        Some(version) if version <= VERSION as u64 => {
+            Ok(serde_json::from_value::<Manifest>(metadata)?.pastes)
+        }
+        Some(version) => {
+            bail!("archive version {version} is newer than the supported version {VERSION}")
+        }
+        None => bail!("archive metadata has no version"),
+    }
+}
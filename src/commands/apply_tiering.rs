@@ -0,0 +1,7 @@
+use crate::controllers::paste;
+use crate::App;
+
+pub async fn run(app: App) -> anyhow::Result<()> {
+    paste::apply_tiering(&app).await?;
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use tracing::info;
+
+use crate::controllers::paste;
+use crate::App;
+
+pub async fn run(app: App, key: &str, secret: Option<&str>) -> anyhow::Result<()> {
+    let (hash, count) = paste::block(&app, key, secret).await?;
+    info!("blocked the content of '{key}' and deleted {count} pastes with it: {hash}");
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+
+use tracing::{info, warn};
+
+use crate::controllers::paste;
+use crate::storage::{self, Storage};
+use crate::App;
+
+pub async fn run(app: App, verify: bool, repair: bool) -> anyhow::Result<()> {
+    let pastes = app.database.get_all_pastes().await?;
+    let stored = app
+        .storage
+        .list_objects()
+        .await?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let referenced = pastes
+        .iter()
+        .map(|paste| paste.object_key())
+        .collect::<HashSet<_>>();
+
+    let mut missing_count = 0;
+    for paste in &pastes {
+        if stored.contains(paste.object_key()) {
+            continue;
+        }
+
+        warn!(
+            "paste '{key}' refers to missing object '{object_key}'",
+            key = paste.key,
+            object_key = paste.object_key()
+        );
+        missing_count += 1;
+
+        if repair {
+            app.database.delete_paste(&paste.key).await?;
+            if let Some(hash) = &paste.object_hash {
+                paste::release_object(&app, hash, 1).await?;
+            }
+        }
+    }
+
+    let mut orphan_count = 0;
+    for key in &stored {
+        if referenced.contains(key.as_str()) {
+            continue;
+        }
+
+        warn!("object '{key}' is not referred to by any paste");
+        orphan_count += 1;
+
+        if repair {
+            app.storage.delete_object(key).await?;
+        }
+    }
+
+    let mut corrupt_count = 0;
+    if verify {
+        let mut checked = HashSet::new();
+        for paste in &pastes {
+            let Some(hash) = &paste.object_hash else { continue };
+            if !stored.contains(hash) || !checked.insert(hash) {
+                continue;
+            }
+
+            // private pastes are hashed after encryption, everything else
+            // before compression and encryption. objects which can't even be
+            // opened count as corrupt too
+            let result = async {
+                if paste.secret_hash.is_some() {
+                    storage::hash_data(app.storage.get_object(hash).await?).await
+                } else {
+                    let (data, _) = paste::fetch_data(&app, &paste.key, None, |_| false).await?;
+                    storage::hash_data(data).await
+                }
+            }
+            .await;
+
+            // objects encrypted with a key are named by a keyed hash instead
+            let expected_hash = match &paste.secret_hash {
+                Some(_) => hash,
+                None => paste.content_hash.as_ref().unwrap_or(hash),
+            };
+
+            match result {
+                Ok((_, actual_hash)) if &actual_hash == expected_hash => {}
+                Ok((_, actual_hash)) => {
+                    warn!("object '{hash}' has a mismatched hash of {actual_hash}");
+                    corrupt_count += 1;
+                }
+                Err(e) => {
+                    warn!("object '{hash}' could not be read: {e}");
+                    corrupt_count += 1;
+                }
+            }
+        }
+    }
+
+    info!(
+        "found {missing_count} pastes with missing objects, {orphan_count} orphaned objects and \
+         {corrupt_count} corrupt objects"
+    );
+    if repair && missing_count + orphan_count > 0 {
+        info!("deleted {missing_count} pastes and {orphan_count} objects");
+    }
+
+    Ok(())
+}
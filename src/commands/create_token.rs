@@ -0,0 +1,8 @@
+use crate::controllers::token;
+use crate::App;
+
+pub async fn run(app: App, name: &str, owner: Option<&str>) -> anyhow::Result<()> {
+    let token = token::create(&app, name, owner.unwrap_or(name)).await?;
+    println!("{token}");
+    Ok(())
+}
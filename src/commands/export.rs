@@ -0,0 +1,88 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_tar::{Builder, Header};
+use tokio_util::io::StreamReader;
+use tracing::{info, warn};
+
+use crate::archive::{self, Manifest, PasteRecord, OBJECTS_PREFIX, PASTES_PATH};
+use crate::compression::into_io_error;
+use crate::error::AppError;
+use crate::storage::Storage;
+use crate::App;
+
+pub async fn run(app: App, path: &Path) -> anyhow::Result<()> {
+    // read in a single query, so that the metadata is consistent
+    let pastes = app.database.get_all_pastes().await?;
+    let keys = pastes
+        .iter()
+        .map(|paste| paste.object_key().to_owned())
+        .collect::<BTreeSet<_>>();
+
+    let file = fs::File::create(path)
+        .await
+        .with_context(|| format!("failed to create {path:?}"))?;
+    let mut builder = Builder::new(file);
+
+    info!(
+        "exporting {} pastes with {} objects",
+        pastes.len(),
+        keys.len()
+    );
+
+    let mut missing = HashSet::new();
+    for key in keys {
+        // objects are only deleted once nothing refers to them, so a missing
+        // object belongs to pastes deleted since the metadata was read
+        let size = match app.storage.get_object_size(&key).await {
+            Ok(size) => size,
+            Err(AppError::NotFound) => {
+                warn!("object '{key}' was deleted during the export, skipping");
+                missing.insert(key);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let data = Box::pin(app.storage.get_object(&key).await?);
+        let mut header = Header::new_gnu();
+        header.set_size(size as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(
+                &mut header,
+                format!("{OBJECTS_PREFIX}{key}"),
+                StreamReader::new(data.map_err(into_io_error)),
+            )
+            .await
+            .with_context(|| format!("failed to export object '{key}'"))?;
+    }
+
+    // the metadata goes last, so that it only includes pastes whose objects
+    // were exported
+    let manifest = Manifest {
+        version: archive::VERSION,
+        pastes: pastes
+            .into_iter()
+            .filter(|paste| !missing.contains(paste.object_key()))
+            .map(PasteRecord::from)
+            .collect(),
+    };
+    let metadata = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = Header::new_gnu();
+    header.set_size(metadata.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, PASTES_PATH, metadata.as_slice())
+        .await?;
+
+    builder.into_inner().await?.flush().await?;
+
+    info!("exported {} pastes to {path:?}", manifest.pastes.len());
+
+    Ok(())
+}
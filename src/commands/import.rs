@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use futures_util::StreamExt;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio_tar::Archive;
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+use crate::archive::{self, PasteRecord, OBJECTS_PREFIX, PASTES_PATH};
+use crate::error::AppError;
+use crate::storage::Storage;
+use crate::App;
+
+/// Restore pastes from an archive written by the export command.
+///
+/// Pastes which already exist are left alone, so that an interrupted import
+/// can be run again. Encrypted pastes can only be read if the keys they were
+/// encrypted with are configured.
+pub async fn run(app: App, path: &Path) -> anyhow::Result<()> {
+    // the metadata comes last in the archive, but is needed to know which
+    // objects to import, so the archive is read twice
+    let pastes = read_pastes(path).await?;
+    let total = pastes.len();
+
+    let mut new_pastes = Vec::new();
+    for paste in pastes {
+        match app.database.get_paste(&paste.key).await {
+            Ok(_) => continue,
+            Err(AppError::NotFound) => new_pastes.push(paste.into_paste()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let needed = new_pastes
+        .iter()
+        .map(|paste| paste.object_key().to_owned())
+        .collect::<BTreeSet<_>>();
+    let sizes = import_objects(&app, path, &needed).await?;
+
+    let mut placed = HashSet::new();
+    let mut count = 0;
+    for mut paste in new_pastes {
+        let object_key = paste.object_key().to_owned();
+        let size = *sizes
+            .get(&object_key)
+            .with_context(|| format!("archive is missing the object of paste '{}'", paste.key))?;
+        let upload_key = upload_key(&object_key);
+
+        match paste.object_hash.clone() {
+            Some(hash) => {
+                let object = app
+                    .database
+                    .acquire_object(
+                        &hash,
+                        size,
+                        paste.encoding.as_deref(),
+                        paste.key_id.as_deref(),
+                    )
+                    .await?;
+
+                if placed.insert(object_key) {
+                    if object.ref_count == 1 {
+                        app.storage.rename_object(&upload_key, &hash).await?;
+                    } else {
+                        // keep the existing data, which its metadata describes
+                        info!("reusing existing object for '{}'", paste.key);
+                        app.storage.delete_object(&upload_key).await?;
+                    }
+                }
+
+                paste.encoding = object.encoding;
+                paste.key_id = object.key_id;
+                paste.tier = object.tier;
+            }
+            None => {
+                if placed.insert(object_key.clone()) {
+                    app.storage.rename_object(&upload_key, &object_key).await?;
+                }
+                paste.tier = None;
+            }
+        }
+
+        app.database.restore_paste(&paste).await?;
+        count += 1;
+    }
+
+    info!("imported {count} pastes, {} already existed", total - count);
+
+    Ok(())
+}
+
+/// Read the paste metadata from an archive.
+async fn read_pastes(path: &Path) -> anyhow::Result<Vec<PasteRecord>> {
+    let mut archive = open_archive(path).await?;
+    let mut entries = archive.entries()?;
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() == PASTES_PATH {
+            let mut metadata = Vec::new();
+            entry.read_to_end(&mut metadata).await?;
+            return archive::parse_pastes(&metadata);
+        }
+    }
+
+    bail!("archive does not contain any paste metadata")
+}
+
+/// Put the needed objects in an archive into storage under temporary keys,
+/// returning their sizes.
+///
+/// Objects only take the place of existing ones once it is known to be safe.
+async fn import_objects(
+    app: &App,
+    path: &Path,
+    needed: &BTreeSet<String>,
+) -> anyhow::Result<HashMap<String, usize>> {
+    let mut archive = open_archive(path).await?;
+    let mut entries = archive.entries()?;
+
+    let mut sizes = HashMap::new();
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+
+        if entry_path == PASTES_PATH {
+            continue;
+        }
+        let Some(key) = entry_path.strip_prefix(OBJECTS_PREFIX) else {
+            warn!("skipping unknown archive entry '{entry_path}'");
+            continue;
+        };
+        if key.is_empty() || key.contains('/') {
+            bail!("invalid object path '{entry_path}' in archive");
+        }
+        if !needed.contains(key) {
+            continue;
+        }
+
+        let size = app
+            .storage
+            .put_object(&upload_key(key), ReaderStream::new(entry))
+            .await
+            .with_context(|| format!("failed to import object '{key}'"))?;
+        sizes.insert(key.to_owned(), size);
+    }
+
+    Ok(sizes)
+}
+
+async fn open_archive(path: &Path) -> anyhow::Result<Archive<fs::File>> {
+    let file = fs::File::open(path)
+        .await
+        .with_context(|| format!("failed to open {path:?}"))?;
+    Ok(Archive::new(file))
+}
+
+/// The temporary key an object is imported under.
+fn upload_key(object_key: &str) -> String {
+    format!("import-{object_key}")
+}
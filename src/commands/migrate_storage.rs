@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Context};
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::config::StorageKind;
+use crate::error::AppError;
+use crate::storage::{self, AnyStorage, Storage};
+use crate::App;
+
+pub async fn run(
+    app: App,
+    from: StorageKind,
+    to: StorageKind,
+    delete_source: bool,
+) -> anyhow::Result<()> {
+    if from == to {
+        bail!("source and destination storage are the same");
+    }
+    // memory storage starts out empty and is gone once the migration exits
+    if from == StorageKind::Memory || to == StorageKind::Memory {
+        bail!("memory storage can't be migrated from or to");
+    }
+
+    let source = AnyStorage::from_config(&app.config.storage, from)
+        .await
+        .context("failed to set up source storage")?;
+    let destination = AnyStorage::from_config(&app.config.storage, to)
+        .await
+        .context("failed to set up destination storage")?;
+
+    let pastes = app.database.get_all_pastes().await?;
+    let keys = pastes
+        .iter()
+        .map(|paste| paste.object_key().to_owned())
+        .collect::<BTreeSet<_>>();
+
+    info!("migrating {} objects from {from:?} to {to:?}", keys.len());
+
+    let mut count = 0;
+    for key in &keys {
+        if migrate_object(&source, &destination, key, delete_source)
+            .await
+            .with_context(|| format!("failed to migrate object '{key}'"))?
+        {
+            count += 1;
+        }
+    }
+
+    info!(
+        "copied {count} objects, {} were already migrated",
+        keys.len() - count
+    );
+
+    Ok(())
+}
+
+/// Copy an object between storage backends, returning whether it had to be
+/// copied.
+///
+/// Objects which are already present in the destination are left alone, so
+/// that an interrupted migration can be resumed.
+async fn migrate_object(
+    source: &AnyStorage,
+    destination: &AnyStorage,
+    key: &str,
+    delete_source: bool,
+) -> anyhow::Result<bool> {
+    let source_size = match source.get_object_size(key).await {
+        Ok(size) => size,
+        Err(AppError::NotFound) => {
+            // the source may have been deleted by a previous run
+            destination
+                .get_object_size(key)
+                .await
+                .context("object is missing from both source and destination")?;
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let already_copied = match destination.get_object_size(key).await {
+        Ok(size) if size == source_size => {
+            hash_object(source, key).await? == hash_object(destination, key).await?
+        }
+        Ok(_) | Err(AppError::NotFound) => false,
+        Err(e) => return Err(e.into()),
+    };
+
+    if !already_copied {
+        let mut hasher = Sha256::new();
+        let data = Box::pin(source.get_object(key).await?).map_ok(|chunk| {
+            hasher.update(&chunk);
+            chunk
+        });
+        let size = destination.put_object(key, data).await?;
+        let hash = hex::encode(hasher.finalize());
+
+        if (size, hash) != hash_object(destination, key).await? {
+            bail!("copied object does not match the source");
+        }
+    }
+
+    if delete_source {
+        source.delete_object(key).await?;
+    }
+
+    Ok(!already_copied)
+}
+
+/// Read an object, returning its size and hash.
+async fn hash_object(storage: &AnyStorage, key: &str) -> crate::AppResult<(usize, String)> {
+    storage::hash_data(storage.get_object(key).await?).await
+}
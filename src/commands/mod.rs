@@ -1,2 +1,10 @@
+pub mod apply_tiering;
+pub mod block_paste;
+pub mod check;
+pub mod create_token;
+pub mod export;
+pub mod import;
+pub mod migrate_storage;
 pub mod purge_expired;
+pub mod revoke_token;
 pub mod serve;
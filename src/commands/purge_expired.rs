@@ -1,7 +1,7 @@
 use crate::controllers::paste;
 use crate::App;
 
-pub async fn run(mut app: App) -> anyhow::Result<()> {
-    paste::purge_expired(&mut app).await?;
+pub async fn run(app: App) -> anyhow::Result<()> {
+    paste::purge_expired(&app).await?;
     Ok(())
 }
@@ -0,0 +1,7 @@
+use crate::controllers::token;
+use crate::App;
+
+pub async fn run(app: App, name: &str) -> anyhow::Result<()> {
+    token::revoke(&app, name).await?;
+    Ok(())
+}
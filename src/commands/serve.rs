@@ -1,50 +1,143 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::Path as FilePath;
+use std::sync::Arc;
 
 use axum::body::Body;
-use axum::extract::{DefaultBodyLimit, Multipart, Path, Query, State};
-use axum::headers::{ContentType, UserAgent};
-use axum::http::{header, StatusCode};
-use axum::response::{IntoResponse, Redirect, Response};
-use axum::routing::get;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Form, Multipart, Path, Query, RawQuery, State};
+use axum::handler::Handler;
+use axum::headers::authorization::Bearer;
+use axum::headers::{Authorization, ContentLength, ContentType, UserAgent};
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router, ServiceExt, TypedHeader};
+use serde::Deserialize;
 use serde_json::json;
 use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::normalize_path::NormalizePath;
 use tower_http::trace::TraceLayer;
+use tracing::{debug_span, info, warn, Span};
 use urlencoding::encode;
 
-use crate::controllers::paste;
+use crate::access::RouteGroup;
+use crate::compression::{self, Encoding};
+use crate::config::Config;
+use crate::controllers::{paste, quota, token};
 use crate::error::AppError;
 use crate::markdown::{markdown_to_ansi, markdown_to_html};
+use crate::models::ApiToken;
+use crate::proxy::{self, BaseUrl, ClientIp};
 use crate::App;
 
 /// The manual for the program written in Markdown.
 const MAN_PAGE: &str = include_str!("../../assets/man.md");
 
-pub async fn run(app: App) -> anyhow::Result<()> {
+/// The page asking to confirm the deletion of a paste.
+const DELETE_PAGE: &str = include_str!("../../assets/delete.html");
+
+/// The page shown once a paste was deleted from a browser.
+const DELETED_PAGE: &str = include_str!("../../assets/deleted.html");
+
+/// The number of pastes listed per page unless asked otherwise.
+const DEFAULT_PER_PAGE: u32 = 50;
+
+/// The most pastes that can be listed per page.
+const MAX_PER_PAGE: u32 = 100;
+
+pub async fn run(app: App, config_path: &FilePath) -> anyhow::Result<()> {
     let addr = SocketAddr::from(([127, 0, 0, 1], app.config.port));
 
+    // shared by reference, since the app is too large to clone per request
+    let app = Arc::new(app);
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(app.clone(), config_path.to_owned()));
+
+    let uploads = middleware::from_fn_with_state(app.clone(), guard_uploads);
+    let downloads = middleware::from_fn_with_state(app.clone(), guard_downloads);
+    let download_access = middleware::from_fn_with_state(app.clone(), check_download_access);
+    let deletes = middleware::from_fn_with_state(app.clone(), guard_deletes);
+
     let app = NormalizePath::trim_trailing_slash(
         Router::new()
-            .route("/", get(index).post(upload_paste))
-            .route("/:id", get(get_paste_bare).delete(delete_paste))
-            .route("/:id/:file_name", get(get_paste))
+            .route("/", get(index).post(upload_paste.layer(uploads)))
+            .route("/api/pastes", get(list_pastes))
+            .route(
+                "/api/pastes/:id",
+                delete(delete_owned_paste.layer(deletes.clone())),
+            )
+            .route("/api/pastes/:id/extend", post(extend_paste))
+            .route(
+                "/delete/:id",
+                get(delete_page.layer(download_access.clone()))
+                    .post(delete_paste_form.layer(deletes.clone())),
+            )
+            // only the data route counts towards the download limit, since
+            // the bare one redirects to it
+            .route(
+                "/:id",
+                get(get_paste_bare.layer(download_access))
+                    .delete(delete_paste.layer(deletes.clone())),
+            )
+            .route(
+                "/:id/:file_name",
+                get(get_paste.layer(downloads)).delete(delete_paste.layer(deletes)),
+            )
             .layer(DefaultBodyLimit::disable())
             .layer(RequestBodyLimitLayer::new(
                 app.config.limits.max_upload_size,
             ))
-            .layer(TraceLayer::new_for_http())
+            .layer(TraceLayer::new_for_http().make_span_with({
+                let app = app.clone();
+                move |request: &Request<Body>| make_span(&app, request)
+            }))
             .with_state(app),
     );
 
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
+/// Reload the parts of the config which can change while the service is
+/// running whenever it receives SIGHUP.
+#[cfg(unix)]
+async fn reload_on_hangup(app: Arc<App>, config_path: std::path::PathBuf) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        info!("reloading access lists from {config_path:?}");
+        match Config::load(&config_path).await {
+            Ok(config) => app.access.reload(config.access.as_ref()),
+            Err(e) => warn!("failed to reload config, keeping the old access lists: {e:#}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Start the span for a request, recording the client it was made by.
+///
+/// Only the path is recorded, since the query may hold secrets and delete keys.
+fn make_span<B>(app: &App, request: &Request<B>) -> Span {
+    let connect_info = request.extensions().get::<ConnectInfo<SocketAddr>>();
+    let client_ip = connect_info.map(|ConnectInfo(peer_addr)| {
+        proxy::client_ip(&app.config, peer_addr.ip(), request.headers())
+    });
+
+    debug_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        client_ip = ?client_ip,
+    )
+}
+
 async fn index(
     TypedHeader(user_agent): TypedHeader<UserAgent>,
 ) -> (TypedHeader<ContentType>, String) {
@@ -59,56 +152,120 @@ async fn index(
 }
 
 async fn get_paste_bare(
-    mut app: State<App>,
+    app: State<Arc<App>>,
     Path(key): Path<String>,
+    RawQuery(query): RawQuery,
 ) -> crate::AppResult<Redirect> {
-    if paste::is_expired(&mut app, &key).await? {
+    if paste::is_expired(&app, &key).await? {
         return Err(AppError::NotFound);
     }
 
     let file_name = app.database.get_paste(&key).await?.file_name;
 
-    Ok(Redirect::permanent(&format!("/{key}/{file_name}")))
+    // keep the query, which may hold the secret of a private paste, in a
+    // redirect which browsers won't cache
+    Ok(match query {
+        Some(query) => Redirect::temporary(&format!("/{key}/{file_name}?{query}")),
+        None => Redirect::permanent(&format!("/{key}/{file_name}")),
+    })
 }
 
 async fn get_paste(
-    mut app: State<App>,
+    app: State<Arc<App>>,
     Path((key, _file_name)): Path<(String, String)>,
-) -> crate::AppResult<Response<Body>> {
-    if paste::is_expired(&mut app, &key).await? {
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> crate::AppResult<Response> {
+    if paste::is_expired(&app, &key).await? {
         return Err(AppError::NotFound);
     }
 
-    let body = Body::wrap_stream(paste::fetch_data(&mut app, &key).await?);
-    let response = Response::builder().body(body)?;
+    let accepts = |encoding: Encoding| compression::is_accepted(&headers, encoding);
 
-    Ok(response)
+    // let the storage backend serve the data directly if it can
+    if let Some(url) = paste::fetch_url(&app, &key, accepts).await? {
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+
+    let secret = params.get("secret").map(String::as_str);
+    let (data, encoding) = paste::fetch_data(&app, &key, secret, accepts).await?;
+
+    let mut response = Response::builder().header(header::VARY, header::ACCEPT_ENCODING);
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding.name());
+    }
+    let response = response.body(Body::wrap_stream(data))?;
+
+    Ok(response.into_response())
 }
 
 async fn upload_paste(
-    mut app: State<App>,
+    app: State<Arc<App>>,
+    ClientIp(client_ip): ClientIp,
+    BaseUrl(base_url): BaseUrl,
+    Query(params): Query<HashMap<String, String>>,
+    content_length: Option<TypedHeader<ContentLength>>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
     mut multipart: Multipart,
 ) -> crate::AppResult<impl IntoResponse> {
+    let private = params.get("private").map_or(false, |value| value == "true");
+
+    let token = match authorization {
+        Some(TypedHeader(Authorization(bearer))) => {
+            Some(token::authenticate(&app, bearer.token()).await?)
+        }
+        None if app.config.require_auth == Some(true) => return Err(AppError::MissingToken),
+        None => None,
+    };
+
+    // reject uploads which are known not to fit before reading them
+    let content_length = content_length.map(|TypedHeader(ContentLength(length))| length);
+    if let Some(content_length) = content_length {
+        paste::check_storage(&app, content_length).await?;
+    }
+    quota::check(&app, client_ip, token.as_ref(), content_length).await?;
+
     // just take the first multipart field
     if let Some(field) = multipart.next_field().await? {
         let file_name = field
             .file_name()
             .ok_or_else(|| AppError::MissingFileName)?
             .to_owned();
+        let content_type = field.content_type().map(str::to_owned);
 
-        let paste = paste::create(&mut app, &file_name, field).await?;
+        let new_paste = paste::create(
+            &app,
+            &file_name,
+            content_type.as_deref(),
+            field,
+            private,
+            token.as_ref(),
+        )
+        .await?;
+        quota::record(&app, client_ip, token.as_ref(), new_paste.received).await?;
+        let paste = new_paste.paste;
 
         let encoded_file_name = encode(&file_name);
-        let path = format!("/{key}/{encoded_file_name}", key = paste.key);
-        let url = format!("{base_url}{path}", base_url = app.config.base_url);
+        let mut path = format!("/{key}/{encoded_file_name}", key = paste.key);
+        if let Some(secret) = new_paste.secret {
+            path = format!("{path}?secret={secret}");
+        }
+        let url = format!("{base_url}{path}");
+        // the delete key goes in the fragment, which browsers never send
+        let delete_url = format!(
+            "{base_url}/delete/{key}#delete_key={delete_key}",
+            key = paste.key,
+            delete_key = new_paste.delete_key,
+        );
 
         Ok((
             StatusCode::CREATED,
             [(header::LOCATION, path)],
             Json(json!({
                 "id": paste.key,
-                "delete_key": paste.delete_key,
+                "delete_key": new_paste.delete_key,
                 "url": url,
+                "delete_url": delete_url,
             }
             )),
         ))
@@ -117,22 +274,210 @@ async fn upload_paste(
     }
 }
 
+/// The path of a paste, with or without its file name.
+#[derive(Deserialize)]
+struct PastePath {
+    id: String,
+}
+
 async fn delete_paste(
-    mut app: State<App>,
+    app: State<Arc<App>>,
+    Path(PastePath { id: key }): Path<PastePath>,
     Query(params): Query<HashMap<String, String>>,
-    Path(key): Path<String>,
+    headers: HeaderMap,
+    form: Option<Form<HashMap<String, String>>>,
 ) -> crate::AppResult<impl IntoResponse> {
-    let delete_key = params
-        .get("delete_key")
+    // owners can delete their pastes with an API token instead of the key
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if let Some(token) = bearer {
+        let token = token::authenticate(&app, token).await?;
+        return paste::delete_owned(&app, &key, &token.owner).await;
+    }
+
+    // prefer headers, since query strings end up in logs
+    let delete_key = headers
+        .get("x-delete-key")
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| {
+            form.as_ref()
+                .and_then(|form| form.get("delete_key"))
+                .map(String::as_str)
+        })
+        .or_else(|| params.get("delete_key").map(String::as_str))
         .ok_or_else(|| AppError::MissingDeleteKey)?;
 
-    // compare against the actual delete key if it exists
-    if let Some(real_delete_key) = app.database.get_paste(&key).await?.delete_key {
-        if delete_key == &real_delete_key {
-            paste::delete(&mut app, &key).await?;
-            return Ok(());
-        }
+    paste::delete_with_key(&app, &key, delete_key).await
+}
+
+/// Ask for confirmation before deleting a paste, since browsers can only
+/// submit forms.
+///
+/// The page fills in the delete key from the URL's fragment itself, so that
+/// the key never reaches the server until the form is submitted.
+async fn delete_page(
+    app: State<Arc<App>>,
+    Path(key): Path<String>,
+) -> crate::AppResult<Html<String>> {
+    let file_name = app.database.get_paste(&key).await?.file_name;
+
+    Ok(Html(
+        DELETE_PAGE
+            .replace("{key}", &escape_html(&key))
+            .replace("{file_name}", &escape_html(&file_name)),
+    ))
+}
+
+async fn delete_paste_form(
+    app: State<Arc<App>>,
+    path: Path<PastePath>,
+    query: Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    form: Option<Form<HashMap<String, String>>>,
+) -> crate::AppResult<Html<&'static str>> {
+    delete_paste(app, path, query, headers, form).await?;
+    Ok(Html(DELETED_PAGE))
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+async fn list_pastes(
+    app: State<Arc<App>>,
+    BaseUrl(base_url): BaseUrl,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Query(pagination): Query<Pagination>,
+) -> crate::AppResult<impl IntoResponse> {
+    let token = authenticate(&app, authorization).await?;
+
+    let page = pagination.page.unwrap_or(1).max(1);
+    let per_page = pagination
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let pastes = paste::list_owned(&app, &token.owner, page, per_page).await?;
+
+    let pastes = pastes
+        .iter()
+        .map(|paste| {
+            json!({
+                "id": paste.key,
+                "file_name": paste.file_name,
+                "url": format!(
+                    "{base_url}/{key}/{encoded_file_name}",
+                    key = paste.key,
+                    encoded_file_name = encode(&paste.file_name),
+                ),
+                "timestamp": paste.timestamp,
+                "expires_at": paste::expiration_time(&app, paste),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "pastes": pastes,
+        "page": page,
+        "per_page": per_page,
+    })))
+}
+
+async fn delete_owned_paste(
+    app: State<Arc<App>>,
+    Path(key): Path<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> crate::AppResult<impl IntoResponse> {
+    let token = authenticate(&app, authorization).await?;
+    paste::delete_owned(&app, &key, &token.owner).await
+}
+
+async fn extend_paste(
+    app: State<Arc<App>>,
+    Path(key): Path<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> crate::AppResult<impl IntoResponse> {
+    let token = authenticate(&app, authorization).await?;
+    let expires_at = paste::extend_owned(&app, &key, &token.owner).await?;
+    Ok(Json(json!({ "expires_at": expires_at })))
+}
+
+async fn guard_uploads<B>(
+    app: State<Arc<App>>,
+    ClientIp(client_ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> crate::AppResult<Response> {
+    app.access.check(RouteGroup::Upload, client_ip)?;
+    if let Some(limiter) = &app.rate_limiters.upload {
+        limiter.acquire(client_ip)?;
     }
+    Ok(next.run(request).await)
+}
+
+async fn guard_downloads<B>(
+    app: State<Arc<App>>,
+    ClientIp(client_ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> crate::AppResult<Response> {
+    app.access.check(RouteGroup::Download, client_ip)?;
+    if let Some(limiter) = &app.rate_limiters.download {
+        limiter.acquire(client_ip)?;
+    }
+    Ok(next.run(request).await)
+}
+
+/// Check access to downloads without counting towards the download limit, for
+/// routes which reveal a paste exists but not its data.
+async fn check_download_access<B>(
+    app: State<Arc<App>>,
+    ClientIp(client_ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> crate::AppResult<Response> {
+    app.access.check(RouteGroup::Download, client_ip)?;
+    Ok(next.run(request).await)
+}
+
+/// Check access to deletions, and limit attempts at guessing delete keys by
+/// only counting the ones which fail.
+async fn guard_deletes<B>(
+    app: State<Arc<App>>,
+    ClientIp(client_ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> crate::AppResult<Response> {
+    app.access.check(RouteGroup::Delete, client_ip)?;
+    let Some(limiter) = &app.rate_limiters.failed_delete else {
+        return Ok(next.run(request).await);
+    };
+
+    limiter.check(client_ip)?;
+
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        limiter.consume(client_ip);
+    }
+    Ok(response)
+}
+
+/// Check the API token a request was made with.
+async fn authenticate(
+    app: &App,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> crate::AppResult<ApiToken> {
+    let TypedHeader(Authorization(bearer)) = authorization.ok_or(AppError::MissingToken)?;
+    token::authenticate(app, bearer.token()).await
+}
 
-    Err(AppError::WrongDeleteKey)
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
 }
@@ -0,0 +1,113 @@
+use std::io;
+
+use async_compression::tokio::bufread::{GzipDecoder, GzipEncoder, ZstdDecoder, ZstdEncoder};
+use axum::http::{header, HeaderMap};
+use bytes::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use serde::Deserialize;
+use tokio_util::either::Either;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::error::AppError;
+
+/// A content encoding that objects can be compressed with at rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    /// The name of the encoding as used in `Content-Encoding`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Parse an encoding from its name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Encoding::Gzip),
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a file is worth compressing, judging by its declared content type,
+/// or the one implied by its name if it has no more specific one.
+pub fn is_compressible(content_type: Option<&str>, file_name: &str) -> bool {
+    // clients declare anything they don't recognize as a stream of bytes
+    let mime = content_type
+        .and_then(|content_type| content_type.parse::<mime_guess::Mime>().ok())
+        .filter(|mime| *mime != mime_guess::mime::APPLICATION_OCTET_STREAM)
+        .unwrap_or_else(|| mime_guess::from_path(file_name).first_or_octet_stream());
+
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("image", "svg") => true,
+        ("image" | "audio" | "video" | "font", _) => false,
+        (
+            "application",
+            "zip" | "gzip" | "x-gzip" | "zstd" | "x-bzip" | "x-bzip2" | "x-xz" | "x-7z-compressed"
+            | "vnd.rar" | "x-rar-compressed" | "pdf" | "epub+zip" | "java-archive",
+        ) => false,
+        _ => true,
+    }
+}
+
+/// Whether a request's `Accept-Encoding` header allows the given encoding.
+pub fn is_accepted(headers: &HeaderMap, encoding: Encoding) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            // a quality of zero means the encoding is explicitly refused
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map_or(false, |q| q == 0.0)
+            });
+            name.eq_ignore_ascii_case(encoding.name()) && !refused
+        })
+}
+
+/// Compress a stream of data.
+pub fn encode<S, E>(encoding: Encoding, data: S) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<AppError>,
+{
+    let reader = StreamReader::new(data.map_err(|e| into_io_error(e.into())));
+    let encoder = match encoding {
+        Encoding::Gzip => Either::Left(GzipEncoder::new(reader)),
+        Encoding::Zstd => Either::Right(ZstdEncoder::new(reader)),
+    };
+    ReaderStream::new(encoder)
+}
+
+/// Decompress a stream of data.
+pub fn decode<S>(encoding: Encoding, data: S) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = Result<Bytes, AppError>>,
+{
+    let reader = StreamReader::new(data.map_err(into_io_error));
+    let decoder = match encoding {
+        Encoding::Gzip => Either::Left(GzipDecoder::new(reader)),
+        Encoding::Zstd => Either::Right(ZstdDecoder::new(reader)),
+    };
+    ReaderStream::new(decoder)
+}
+
+/// Wrap an error so that it survives being passed through a reader and can be
+/// recovered when converted back.
+pub fn into_io_error(error: AppError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
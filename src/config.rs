@@ -1,26 +1,56 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use anyhow::Context;
+use clap::ValueEnum;
+use ipnet::IpNet;
 use serde::Deserialize;
+use tokio::fs;
+
+use crate::compression::Encoding;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub base_url: String,
     pub port: u16,
+    pub require_auth: Option<bool>,
+    pub trusted_proxies: Option<Vec<IpNet>>,
+    pub trust_forwarded_host: Option<bool>,
     pub database: Database,
     pub storage: Storage,
+    pub encryption: Option<Encryption>,
     pub limits: Limits,
+    pub access: Option<Access>,
     pub word_lists: WordLists,
 }
 
+impl Config {
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .await
+            .context("failed to read config file")?;
+        toml::from_str(&contents).context("failed to parse config file")
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Database {
     pub url: String,
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub acquire_timeout_secs: Option<u64>,
+    pub idle_timeout_secs: Option<u64>,
+    pub sqlite_wal: Option<bool>,
+    pub sqlite_busy_timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Storage {
     pub kind: StorageKind,
+    pub compression: Option<Encoding>,
     pub file: FileStorage,
+    pub memory: Option<MemoryStorage>,
+    pub mirror: Option<MirrorStorage>,
+    pub tiered: Option<TieredStorage>,
     #[cfg(feature = "s3")]
     pub s3: S3Storage,
 }
@@ -30,26 +60,135 @@ pub struct FileStorage {
     pub dir: PathBuf,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemoryStorage {
+    pub max_size: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MirrorStorage {
+    pub primary: Backend,
+    pub replicas: Vec<Backend>,
+    pub retry_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TieredStorage {
+    pub hot: FileStorage,
+    pub cold: Backend,
+    pub max_hot_age_secs: Option<u64>,
+    pub max_disk_usage_percent: Option<u8>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Backend {
+    File(FileStorage),
+    #[cfg(feature = "s3")]
+    S3(S3Storage),
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[cfg(feature = "s3")]
 pub struct S3Storage {
     pub bucket: String,
     pub region: Option<String>,
     pub endpoint: Option<String>,
+    pub force_path_style: Option<bool>,
+    pub key_prefix: Option<String>,
+    pub storage_class: Option<String>,
+    pub profile: Option<String>,
+    pub credentials: Option<S3Credentials>,
+    pub max_attempts: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub connect_timeout_secs: Option<u64>,
+    pub read_timeout_secs: Option<u64>,
+    pub operation_timeout_secs: Option<u64>,
+    pub presign_expiration_secs: Option<u64>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+#[cfg(feature = "s3")]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum StorageKind {
     File,
+    Memory,
+    Mirror,
+    Tiered,
     #[cfg(feature = "s3")]
     S3,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct Encryption {
+    pub current_key_id: String,
+    pub keys: Vec<EncryptionKey>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EncryptionKey {
+    pub id: String,
+    pub key: Option<String>,
+    pub key_file: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Limits {
     pub max_upload_size: usize,
+    pub max_storage_size: Option<u64>,
+    pub min_free_space: Option<u64>,
+    pub quotas: Option<Vec<Quota>>,
+    pub rate_limits: Option<RateLimits>,
     pub expiration_secs: Option<u64>,
+    pub deleted_retention_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Quota {
+    pub key: QuotaKey,
+    pub window_secs: u64,
+    pub max_bytes: Option<u64>,
+    pub max_pastes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaKey {
+    Token,
+    Ip,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimits {
+    pub upload: Option<RateLimit>,
+    pub download: Option<RateLimit>,
+    pub failed_delete: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Access {
+    pub upload: Option<AccessList>,
+    pub download: Option<AccessList>,
+    pub delete: Option<AccessList>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessList {
+    pub allow: Option<Vec<IpNet>>,
+    pub deny: Option<Vec<IpNet>>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
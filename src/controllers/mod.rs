@@ -1 +1,3 @@
 pub mod paste;
+pub mod quota;
+pub mod token;
@@ -1,23 +1,109 @@
+use std::collections::HashMap;
+
 use axum::body::Bytes;
-use chrono::{DateTime, Utc};
-use futures_util::Stream;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::future::Either;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use tracing::{info, warn};
 use uuid::Uuid;
 
+use crate::compression::{self, Encoding};
+use crate::encryption::{self, Key};
 use crate::error::AppError;
-use crate::models::Paste;
-use crate::storage::Storage;
+use crate::models::{self, ApiToken, Paste};
+use crate::storage::tiered::Tier;
+use crate::storage::{self, AnyStorage, Storage};
 use crate::words::generate_key;
 use crate::{App, AppResult};
 
+/// How long deleted pastes are remembered by default, in seconds.
+const DEFAULT_DELETED_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Fetch a paste's data, along with the encoding it is compressed with.
+///
+/// The data is only left compressed if its encoding is accepted.
 pub async fn fetch_data(
-    app: &mut App,
+    app: &App,
     key: &str,
-) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
-    app.storage.get_object(key).await
+    secret: Option<&str>,
+    accepts: impl Fn(Encoding) -> bool,
+) -> crate::AppResult<(impl Stream<Item = AppResult<Bytes>>, Option<Encoding>)> {
+    let paste = app.database.get_paste(key).await?;
+    let encryption_key = match verify_secret(&paste, secret)? {
+        Some(secret) => Some(secret),
+        None => paste
+            .key_id
+            .as_deref()
+            .map(|key_id| encryption_key(app, key_id))
+            .transpose()?,
+    };
+
+    let data = app
+        .storage
+        .get_object_in(paste.object_key(), paste.tier())
+        .await?;
+    let data = match encryption_key {
+        Some(encryption_key) => Either::Left(encryption::decrypt(&encryption_key, data)),
+        None => Either::Right(data),
+    };
+
+    Ok(match paste.encoding() {
+        Some(encoding) if !accepts(encoding) => (
+            Either::Right(compression::decode(encoding, data).map_err(AppError::from)),
+            None,
+        ),
+        encoding => (Either::Left(data), encoding),
+    })
+}
+
+pub async fn fetch_url(
+    app: &App,
+    key: &str,
+    accepts: impl Fn(Encoding) -> bool,
+) -> crate::AppResult<Option<String>> {
+    let paste = app.database.get_paste(key).await?;
+
+    // encrypted data needs to be decrypted by the service
+    if paste.key_id.is_some() || paste.secret_hash.is_some() {
+        return Ok(None);
+    }
+
+    // compressed data needs to be decoded by the service
+    let encoding = paste.encoding();
+    if encoding.map_or(false, |encoding| !accepts(encoding)) {
+        return Ok(None);
+    }
+
+    app.storage
+        .get_object_url_in(paste.object_key(), paste.tier(), &paste.file_name, encoding)
+        .await
+}
+
+/// A newly created paste, along with the keys only its creator is given.
+pub struct NewPaste {
+    pub paste: Paste,
+    /// The number of bytes uploaded, before compression and encryption.
+    pub received: u64,
+    pub delete_key: String,
+    pub secret: Option<String>,
 }
 
-pub async fn create<S, E>(app: &mut App, file_name: &str, data: S) -> crate::AppResult<Paste>
+/// Create a paste from a stream of data.
+///
+/// If `private` is set, the paste is encrypted with its own key, which is
+/// returned as a secret and never stored. The API token it was uploaded with,
+/// if any, is recorded with the paste along with its owner.
+pub async fn create<S, E>(
+    app: &App,
+    file_name: &str,
+    content_type: Option<&str>,
+    data: S,
+    private: bool,
+    token: Option<&ApiToken>,
+) -> crate::AppResult<NewPaste>
 where
     S: Stream<Item = Result<Bytes, E>> + Unpin,
     E: Into<AppError>,
@@ -27,24 +113,296 @@ where
 
     info!("new paste: key='{key}', file='{file_name}'");
 
-    let paste = app
+    // stop the upload as soon as it goes over the space that is left
+    let available = available_storage(app).await?.unwrap_or(u64::MAX);
+    let mut received = 0;
+    let data = data.map_err(Into::<AppError>::into).map(|chunk| {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if received > available {
+            return Err(AppError::InsufficientStorage);
+        }
+        Ok(chunk)
+    });
+
+    // upload under a temporary key, since the hash is only known at the end
+    let upload_key = format!("upload-{key}");
+    let mut hasher = Sha256::new();
+    let data = data.map_ok(|chunk| {
+        hasher.update(&chunk);
+        chunk
+    });
+
+    let encoding = app
+        .config
+        .storage
+        .compression
+        .filter(|_| compression::is_compressible(content_type, file_name));
+    let data = match encoding {
+        Some(encoding) => Either::Left(compression::encode(encoding, data).map_err(AppError::from)),
+        None => Either::Right(data),
+    };
+
+    let secret = private.then(|| {
+        let mut secret = Key::default();
+        thread_rng().fill(secret.as_mut_slice());
+        secret
+    });
+
+    // private pastes are identified by the hash of their encrypted data
+    // instead, so that they are never shared and the hash reveals nothing
+    let mut encrypted_hasher = Sha256::new();
+
+    let mut key_id = None;
+    let size = match (&secret, &app.key_ring) {
+        (Some(secret), _) => {
+            let data = encryption::encrypt(secret, data).map_ok(|chunk| {
+                encrypted_hasher.update(&chunk);
+                chunk
+            });
+            app.storage.put_object(&upload_key, data).await
+        }
+        (None, Some(key_ring)) => {
+            key_id = Some(key_ring.current_key_id());
+            let data = encryption::encrypt(key_ring.get(key_ring.current_key_id())?, data);
+            app.storage.put_object(&upload_key, data).await
+        }
+        (None, None) => app.storage.put_object(&upload_key, data).await,
+    };
+    let size = match size {
+        Ok(size) => size,
+        Err(e) => {
+            // don't leave a partial upload behind
+            discard_upload(app, &upload_key).await;
+            return Err(e);
+        }
+    };
+    let content_digest = hasher.finalize();
+    let content_hash = hex::encode(content_digest);
+    let hash = match (&secret, &app.key_ring, key_id) {
+        (Some(_), _, _) => hex::encode(encrypted_hasher.finalize()),
+        // encrypted objects are named by a keyed hash, so that the names in
+        // storage don't reveal what is stored to anyone without the key
+        (None, Some(key_ring), Some(key_id)) => key_ring.object_name(key_id, &content_digest)?,
+        _ => content_hash.clone(),
+    };
+
+    info!("size of '{key}': {size} bytes, hash: {hash}");
+
+    if app.database.is_hash_blocked(&content_hash).await? {
+        warn!("rejecting blocked content for '{key}': {content_hash}");
+        app.storage.delete_object(&upload_key).await?;
+        return Err(AppError::BlockedContent);
+    }
+
+    let object = app
+        .database
+        .acquire_object(
+            &hash,
+            size,
+            encoding.map(|encoding| encoding.name()),
+            key_id,
+        )
+        .await?;
+
+    let secret_hash = secret.map(|secret| hex::encode(Sha256::digest(secret)));
+    let result = async {
+        if object.ref_count == 1 {
+            app.storage.rename_object(&upload_key, &hash).await?;
+        } else {
+            info!("reusing existing object for '{key}'");
+            app.storage.delete_object(&upload_key).await?;
+        }
+
+        app.database
+            .insert_paste(
+                &key,
+                &models::hash_delete_key(&delete_key),
+                file_name,
+                &object,
+                secret.is_none().then_some(content_hash.as_str()),
+                secret_hash.as_deref(),
+                token,
+            )
+            .await
+    }
+    .await;
+    let paste = match result {
+        Ok(paste) => paste,
+        Err(e) => {
+            // the reference was never used, so give it back, or the object
+            // would be left without data or never be freed
+            if let Err(e) = release_object(app, &hash, 1).await {
+                warn!("failed to release object '{hash}' of failed upload '{key}': {e}");
+            }
+            discard_upload(app, &upload_key).await;
+            return Err(e);
+        }
+    };
+
+    Ok(NewPaste {
+        paste,
+        received,
+        delete_key,
+        secret: secret.map(hex::encode),
+    })
+}
+
+/// Delete a paste if the delete key matches.
+pub async fn delete_with_key(app: &App, key: &str, delete_key: &str) -> crate::AppResult<()> {
+    let paste = get_live_paste(app, key).await?;
+
+    if !paste.verify_delete_key(delete_key) {
+        return Err(AppError::WrongDeleteKey);
+    }
+
+    delete_inner(app, &paste).await
+}
+
+/// Delete a paste and block its content from being uploaded again, returning
+/// the hash of the content and the number of pastes deleted.
+///
+/// Every other paste with the same content is deleted along with it. Private
+/// pastes can only be blocked with their secret, since their content has to be
+/// read to be hashed, and other private pastes with the same content can't be
+/// found.
+pub async fn block(
+    app: &App,
+    key: &str,
+    secret: Option<&str>,
+) -> crate::AppResult<(String, usize)> {
+    let paste = app.database.get_paste(key).await?;
+
+    // only private pastes and pastes from before deduplication have no
+    // content hash recorded
+    let content_hash = match &paste.content_hash {
+        Some(hash) => hash.clone(),
+        None => {
+            let (data, _) = fetch_data(app, key, secret, |_| false).await?;
+            storage::hash_data(data).await?.1
+        }
+    };
+
+    // block the content first, so that it can't be uploaded again meanwhile
+    app.database.insert_blocked_hash(&content_hash).await?;
+
+    // pastes with the same content can be stored in more than one object,
+    // when they were encrypted with different keys
+    let shared = app
         .database
-        .insert_paste(&key, &delete_key, file_name)
+        .get_pastes_by_content_hash(&content_hash)
         .await?;
-    let size = app.storage.put_object(&key, data).await?;
+    let mut references = HashMap::<&str, usize>::new();
+    for shared_paste in &shared {
+        app.database.delete_paste(&shared_paste.key).await?;
+        if let Some(hash) = &shared_paste.object_hash {
+            *references.entry(hash).or_default() += 1;
+        }
+    }
+    for (hash, count) in references {
+        release_object(app, hash, count).await?;
+    }
 
-    info!("size of '{key}': {size} bytes");
+    let mut count = shared.len();
+    if !shared
+        .iter()
+        .any(|shared_paste| shared_paste.key == paste.key)
+    {
+        delete_inner(app, &paste).await?;
+        count += 1;
+    }
 
-    Ok(paste)
+    Ok((content_hash, count))
 }
 
-pub async fn delete(app: &mut App, key: &str) -> crate::AppResult<()> {
-    app.database.delete_paste(key).await?;
-    app.storage.delete_object(key).await?;
-    Ok(())
+/// Delete a paste if it belongs to the given owner.
+pub async fn delete_owned(app: &App, key: &str, owner: &str) -> crate::AppResult<()> {
+    let paste = get_live_paste(app, key).await?;
+
+    if paste.owner.as_deref() != Some(owner) {
+        return Err(AppError::Forbidden);
+    }
+
+    delete_inner(app, &paste).await
+}
+
+/// Restart the expiration of a paste which belongs to the given owner,
+/// returning when it now expires.
+pub async fn extend_owned(
+    app: &App,
+    key: &str,
+    owner: &str,
+) -> crate::AppResult<Option<DateTime<Utc>>> {
+    let paste = get_live_paste(app, key).await?;
+
+    if paste.owner.as_deref() != Some(owner) {
+        return Err(AppError::Forbidden);
+    }
+
+    let Some(expiration_secs) = app.config.limits.expiration_secs else { return Ok(None) };
+
+    let expires_at = Utc::now() + Duration::seconds(expiration_secs as i64);
+    app.database.set_expires_at(key, expires_at).await?;
+
+    Ok(Some(expires_at))
+}
+
+/// Get a page of the pastes belonging to an owner, newest first, counting
+/// pages from 1.
+///
+/// Pastes which have expired but weren't purged yet are left out.
+pub async fn list_owned(
+    app: &App,
+    owner: &str,
+    page: u32,
+    per_page: u32,
+) -> crate::AppResult<Vec<Paste>> {
+    let pastes = app.database.get_pastes_by_owner(owner).await?;
+
+    // timestamps aren't stored in the same format by every database, so they
+    // are compared here rather than in the query
+    let now = Utc::now();
+    Ok(pastes
+        .into_iter()
+        .filter(|paste| {
+            app.config
+                .limits
+                .expiration_secs
+                .map_or(true, |expiration_secs| {
+                    !is_expired_inner(paste, &now, expiration_secs)
+                })
+        })
+        .skip((page - 1).saturating_mul(per_page) as usize)
+        .take(per_page as usize)
+        .collect())
+}
+
+/// Get when a paste expires, if pastes expire at all.
+pub fn expiration_time(app: &App, paste: &Paste) -> Option<DateTime<Utc>> {
+    let expiration_secs = app.config.limits.expiration_secs?;
+    Some(
+        paste
+            .expires_at
+            .unwrap_or(paste.timestamp + Duration::seconds(expiration_secs as i64)),
+    )
 }
 
-pub async fn purge_expired(app: &mut App) -> crate::AppResult<()> {
+pub async fn purge_expired(app: &App) -> crate::AppResult<()> {
+    // deleted pastes are only remembered for a while, to tell them apart from
+    // pastes which never existed
+    let retention_secs = app
+        .config
+        .limits
+        .deleted_retention_secs
+        .unwrap_or(DEFAULT_DELETED_RETENTION_SECS);
+    let forgotten = app
+        .database
+        .delete_deleted_pastes_before(Utc::now() - Duration::seconds(retention_secs as i64))
+        .await?;
+    if forgotten > 0 {
+        info!("forgot {forgotten} deleted pastes");
+    }
+
     let Some(expiration_secs) = app.config.limits.expiration_secs else {
         warn!("tried to purge expired, but no expiration limit was set");
         return Ok(());
@@ -57,8 +415,7 @@ pub async fn purge_expired(app: &mut App) -> crate::AppResult<()> {
     let mut count = 0;
     for paste in pastes {
         if is_expired_inner(&paste, &now, expiration_secs) {
-            app.database.delete_paste(&paste.key).await?;
-            app.storage.delete_object(&paste.key).await?;
+            delete_inner(app, &paste).await?;
             count += 1;
         }
     }
@@ -70,7 +427,60 @@ pub async fn purge_expired(app: &mut App) -> crate::AppResult<()> {
     Ok(())
 }
 
-pub async fn is_expired(app: &mut App, key: &str) -> crate::AppResult<bool> {
+/// Move pastes from the hot tier to the cold tier once they are old enough or
+/// the hot tier's disk is too full, oldest first.
+pub async fn apply_tiering(app: &App) -> crate::AppResult<()> {
+    let (Some(tiered_config), AnyStorage::Tiered(storage)) =
+        (&app.config.storage.tiered, &app.storage)
+    else {
+        warn!("tried to apply tiering, but tiered storage is not in use");
+        return Ok(());
+    };
+
+    let pastes = app.database.get_all_pastes().await?;
+
+    // an object is only as old as the newest paste referring to it
+    let mut objects = HashMap::new();
+    for paste in pastes.iter().filter(|paste| paste.tier() == Tier::Hot) {
+        let timestamp = objects.entry(paste.object_key()).or_insert(paste.timestamp);
+        *timestamp = paste.timestamp.max(*timestamp);
+    }
+    let mut objects = objects.into_iter().collect::<Vec<_>>();
+    objects.sort_by_key(|(_, timestamp)| *timestamp);
+
+    let now = Utc::now();
+
+    let mut count = 0;
+    for (key, timestamp) in objects {
+        let too_old = tiered_config
+            .max_hot_age_secs
+            .map_or(false, |max_age_secs| {
+                (now - timestamp).num_seconds() > max_age_secs as i64
+            });
+        let too_full = match tiered_config.max_disk_usage_percent {
+            Some(max_usage) => storage.disk_usage()? * 100.0 > max_usage as f64,
+            None => false,
+        };
+
+        // objects are sorted oldest first, so none of the rest need moving
+        if !too_old && !too_full {
+            break;
+        }
+
+        storage.copy_to_cold(key).await?;
+        app.database.set_tier(key, Tier::Cold.name()).await?;
+        storage.backend(Tier::Hot).delete_object(key).await?;
+        count += 1;
+    }
+
+    if count > 0 {
+        info!("moved {count} objects to the cold tier");
+    }
+
+    Ok(())
+}
+
+pub async fn is_expired(app: &App, key: &str) -> crate::AppResult<bool> {
     let Some(expiration_secs) = app.config.limits.expiration_secs else { return Ok(false) };
 
     let paste = app.database.get_paste(key).await?;
@@ -78,7 +488,129 @@ pub async fn is_expired(app: &mut App, key: &str) -> crate::AppResult<bool> {
     Ok(is_expired_inner(&paste, &Utc::now(), expiration_secs))
 }
 
+/// Get a paste by key, reporting pastes which were already deleted or have
+/// expired as gone rather than not found.
+async fn get_live_paste(app: &App, key: &str) -> crate::AppResult<Paste> {
+    let paste = match app.database.get_paste(key).await {
+        Err(AppError::NotFound) if app.database.is_paste_deleted(key).await? => {
+            return Err(AppError::Gone)
+        }
+        result => result?,
+    };
+
+    let expired = app
+        .config
+        .limits
+        .expiration_secs
+        .map_or(false, |expiration_secs| {
+            is_expired_inner(&paste, &Utc::now(), expiration_secs)
+        });
+    if expired {
+        return Err(AppError::Gone);
+    }
+
+    Ok(paste)
+}
+
+/// Check that an upload of the given size would fit in the storage that is
+/// left.
+pub async fn check_storage(app: &App, size: u64) -> crate::AppResult<()> {
+    match available_storage(app).await? {
+        Some(available) if size > available => Err(AppError::InsufficientStorage),
+        _ => Ok(()),
+    }
+}
+
+/// Get how many more bytes can be stored before reaching the storage limit or
+/// free space floor, if either is set.
+async fn available_storage(app: &App) -> crate::AppResult<Option<u64>> {
+    let mut available = None;
+
+    if let Some(max_storage_size) = app.config.limits.max_storage_size {
+        let usage = app.database.get_storage_usage().await?;
+        available = Some(max_storage_size.saturating_sub(usage));
+    }
+
+    if let Some(min_free_space) = app.config.limits.min_free_space {
+        if let Some(free_space) = app.storage.available_space()? {
+            let above_floor = free_space.saturating_sub(min_free_space);
+            available = Some(available.map_or(above_floor, |available| available.min(above_floor)));
+        }
+    }
+
+    Ok(available)
+}
+
+/// Check the secret given for a private paste, returning the key its data is
+/// encrypted with.
+fn verify_secret(paste: &Paste, secret: Option<&str>) -> crate::AppResult<Option<Key>> {
+    let Some(secret_hash) = &paste.secret_hash else { return Ok(None) };
+
+    let secret = secret.ok_or(AppError::MissingSecret)?;
+    let secret = hex::decode(secret)
+        .ok()
+        .filter(|secret| secret.len() == 32)
+        .ok_or(AppError::WrongSecret)?;
+
+    let matches: bool = hex::encode(Sha256::digest(&secret))
+        .as_bytes()
+        .ct_eq(secret_hash.as_bytes())
+        .into();
+    if !matches {
+        return Err(AppError::WrongSecret);
+    }
+
+    Ok(Some(*Key::from_slice(&secret)))
+}
+
+fn encryption_key(app: &App, key_id: &str) -> crate::AppResult<Key> {
+    match &app.key_ring {
+        Some(key_ring) => key_ring.get(key_id).copied(),
+        None => Err(AppError::UnknownEncryptionKey {
+            key_id: key_id.to_owned(),
+        }),
+    }
+}
+
+async fn delete_inner(app: &App, paste: &Paste) -> crate::AppResult<()> {
+    app.database.delete_paste(&paste.key).await?;
+
+    // only delete the object once nothing else refers to it
+    match &paste.object_hash {
+        Some(hash) => release_object(app, hash, 1).await?,
+        None => app.storage.delete_object(&paste.key).await?,
+    }
+
+    Ok(())
+}
+
+/// Remove references to an object, deleting it once nothing refers to it.
+pub async fn release_object(app: &App, hash: &str, count: usize) -> crate::AppResult<()> {
+    app.database
+        .release_object(hash, count, || async {
+            match app.storage.delete_object(hash).await {
+                Ok(()) | Err(AppError::NotFound) => Ok(()),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+}
+
+/// Delete an upload which won't be used, logging rather than returning any
+/// failure.
+async fn discard_upload(app: &App, upload_key: &str) {
+    match app.storage.delete_object(upload_key).await {
+        Ok(()) | Err(AppError::NotFound) => {}
+        Err(e) => warn!("failed to delete upload '{upload_key}': {e}"),
+    }
+}
+
 fn is_expired_inner(paste: &Paste, current_time: &DateTime<Utc>, expiration_secs: u64) -> bool {
+    // pastes whose expiration was extended expire at a set time instead
+    if let Some(expires_at) = paste.expires_at {
+        return *current_time > expires_at;
+    }
+
     let elapsed: u64 = (*current_time - paste.timestamp)
         .num_seconds()
         .try_into()
@@ -0,0 +1,84 @@
+use std::net::IpAddr;
+
+use chrono::{Duration, Utc};
+
+use crate::config::QuotaKey;
+use crate::error::AppError;
+use crate::models::ApiToken;
+use crate::App;
+
+/// Check that a client is within its upload quotas, including an upload of
+/// the given size if it is known.
+pub async fn check(
+    app: &App,
+    client_ip: IpAddr,
+    token: Option<&ApiToken>,
+    size: Option<u64>,
+) -> crate::AppResult<()> {
+    let now = Utc::now();
+
+    for quota in app.config.limits.quotas.iter().flatten() {
+        let window = Duration::seconds(quota.window_secs as i64);
+        let usage = match (quota.key, token) {
+            (QuotaKey::Ip, _) => {
+                app.database
+                    .get_upload_usage_by_ip(&client_ip.to_string(), now - window)
+                    .await?
+            }
+            (QuotaKey::Token, Some(token)) => {
+                app.database
+                    .get_upload_usage_by_token(&token.name, now - window)
+                    .await?
+            }
+            (QuotaKey::Token, None) => continue,
+        };
+
+        let over_pastes = quota
+            .max_pastes
+            .map_or(false, |max_pastes| usage.count as u64 >= max_pastes);
+        let over_bytes = quota.max_bytes.map_or(false, |max_bytes| {
+            usage.size as u64 + size.unwrap_or(0) > max_bytes
+        });
+
+        if over_pastes || over_bytes {
+            // the quota frees up as the oldest upload leaves the window
+            let retry_after = usage
+                .oldest
+                .map_or(window, |oldest| oldest + window - now)
+                .num_seconds()
+                .max(1) as u64;
+            return Err(AppError::QuotaExceeded {
+                retry_after_secs: retry_after,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Count an upload against a client's quotas.
+pub async fn record(
+    app: &App,
+    client_ip: IpAddr,
+    token: Option<&ApiToken>,
+    size: u64,
+) -> crate::AppResult<()> {
+    let Some(quotas) = &app.config.limits.quotas else { return Ok(()) };
+
+    // nothing older than the longest window is needed anymore
+    let longest_window_secs = quotas
+        .iter()
+        .map(|quota| quota.window_secs)
+        .max()
+        .unwrap_or(0);
+    let forget_before = Utc::now() - Duration::seconds(longest_window_secs as i64);
+
+    app.database
+        .insert_upload(
+            &client_ip.to_string(),
+            token.map(|token| token.name.as_str()),
+            size,
+            forget_before,
+        )
+        .await
+}
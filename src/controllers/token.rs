@@ -0,0 +1,51 @@
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::error::AppError;
+use crate::models::ApiToken;
+use crate::App;
+
+/// Create an API token for an owner, returning the token itself, which is not
+/// stored.
+pub async fn create(app: &App, name: &str, owner: &str) -> crate::AppResult<String> {
+    let mut token = [0u8; 32];
+    thread_rng().fill(&mut token);
+    let token = hex::encode(token);
+
+    app.database
+        .insert_token(name, owner, &hash_token(&token))
+        .await?;
+
+    info!("created token '{name}' for '{owner}'");
+
+    Ok(token)
+}
+
+/// Revoke an API token by name.
+pub async fn revoke(app: &App, name: &str) -> crate::AppResult<()> {
+    if !app.database.delete_token(name).await? {
+        return Err(AppError::NotFound);
+    }
+
+    info!("revoked token '{name}'");
+
+    Ok(())
+}
+
+/// Check an API token, returning its name and owner.
+pub async fn authenticate(app: &App, token: &str) -> crate::AppResult<ApiToken> {
+    match app.database.get_token(&hash_token(token)).await {
+        Ok(token) => Ok(token),
+        Err(AppError::NotFound) => Err(AppError::InvalidToken),
+        Err(e) => Err(e),
+    }
+}
+
+/// Hash a token for storage.
+///
+/// Tokens are random and long enough that they don't need a salt, which lets
+/// them be looked up by hash.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token))
+}
@@ -1,34 +1,125 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::any::{AnyConnectOptions, AnyKind, AnyPoolOptions};
+#[cfg(feature = "sqlite")]
+use sqlx::sqlite::SqliteJournalMode;
 use sqlx::AnyPool;
+use tracing::info;
 
-use crate::models::Paste;
+use crate::config;
+use crate::models::{self, ApiToken, Object, Paste, UploadUsage};
 
 #[derive(Clone)]
 pub struct Database {
     pool: AnyPool,
+    numbered_placeholders: bool,
 }
 
 impl Database {
-    /// Connect to a database by URL and apply pending migrations.
-    pub async fn connect(url: &str) -> anyhow::Result<Self> {
-        let pool = AnyPool::connect(url).await?;
-        sqlx::migrate!().run(&pool).await?;
-        Ok(Self { pool })
+    /// Connect to a database and apply pending migrations.
+    pub async fn connect(config: &config::Database) -> anyhow::Result<Self> {
+        #[allow(unused_mut)]
+        let mut options = AnyConnectOptions::from_str(&config.url)?;
+
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlite_options) = options.as_sqlite_mut() {
+            let mut new_options = sqlite_options.clone();
+            if let Some(wal) = config.sqlite_wal {
+                new_options = new_options.journal_mode(match wal {
+                    true => SqliteJournalMode::Wal,
+                    false => SqliteJournalMode::Delete,
+                });
+            }
+            if let Some(busy_timeout_ms) = config.sqlite_busy_timeout_ms {
+                new_options = new_options.busy_timeout(Duration::from_millis(busy_timeout_ms));
+            }
+            *sqlite_options = new_options;
+        }
+
+        let mut pool_options = AnyPoolOptions::new();
+        if let Some(max_connections) = config.max_connections {
+            pool_options = pool_options.max_connections(max_connections);
+        }
+        if let Some(min_connections) = config.min_connections {
+            pool_options = pool_options.min_connections(min_connections);
+        }
+        if let Some(acquire_timeout_secs) = config.acquire_timeout_secs {
+            pool_options = pool_options.acquire_timeout(Duration::from_secs(acquire_timeout_secs));
+        }
+        if let Some(idle_timeout_secs) = config.idle_timeout_secs {
+            pool_options = pool_options.idle_timeout(Duration::from_secs(idle_timeout_secs));
+        }
+
+        let pool = pool_options.connect_with(options).await?;
+
+        // queries are written with `?` placeholders, but postgres only
+        // understands numbered ones like `$1`
+        let (migrator, numbered_placeholders) = match pool.any_kind() {
+            #[cfg(feature = "sqlite")]
+            AnyKind::Sqlite => (sqlx::migrate!("migrations/sqlite"), false),
+            #[cfg(feature = "postgres")]
+            AnyKind::Postgres => (sqlx::migrate!("migrations/postgres"), true),
+        };
+        migrator.run(&pool).await?;
+
+        let database = Self {
+            pool,
+            numbered_placeholders,
+        };
+        database.hash_delete_keys().await?;
+
+        Ok(database)
+    }
+
+    /// Replace any delete keys stored in plaintext with their hashes.
+    ///
+    /// Delete keys were stored as-is before they were hashed, which can't be
+    /// done by a migration alone.
+    async fn hash_delete_keys(&self) -> crate::AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        let delete_keys = sqlx::query_as::<_, (String, String)>(
+            &self.sql("SELECT key, delete_key FROM paste WHERE delete_key IS NOT NULL"),
+        )
+        .fetch_all(&mut tx)
+        .await?;
+        for (key, delete_key) in &delete_keys {
+            sqlx::query(
+                &self.sql("UPDATE paste SET delete_key = NULL, delete_key_hash = ? WHERE key = ?"),
+            )
+            .bind(models::hash_delete_key(delete_key))
+            .bind(key)
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        if !delete_keys.is_empty() {
+            info!("hashed {} plaintext delete keys", delete_keys.len());
+        }
+
+        Ok(())
     }
 
     /// Get all pastes.
-    pub async fn get_all_pastes(&mut self) -> crate::AppResult<Vec<Paste>> {
+    pub async fn get_all_pastes(&self) -> crate::AppResult<Vec<Paste>> {
         let mut conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, Paste>("SELECT * FROM paste")
+        Ok(sqlx::query_as::<_, Paste>(&self.sql("SELECT * FROM paste"))
             .fetch_all(&mut conn)
             .await?)
     }
 
     /// Get a paste by key.
-    pub async fn get_paste(&mut self, key: &str) -> crate::AppResult<Paste> {
+    pub async fn get_paste(&self, key: &str) -> crate::AppResult<Paste> {
         let mut conn = self.pool.acquire().await?;
-        let paste = sqlx::query_as::<_, Paste>(
-            "SELECT key, delete_key, timestamp, file_name FROM paste WHERE key = ?",
-        )
+        let paste = sqlx::query_as::<_, Paste>(&self.sql(
+            "SELECT key, delete_key_hash, timestamp, file_name, object_hash, content_hash, \
+             encoding, key_id, secret_hash, tier, token_name, owner, expires_at FROM paste WHERE \
+             key = ?",
+        ))
         .bind(key)
         .fetch_one(&mut conn)
         .await?;
@@ -37,31 +128,529 @@ impl Database {
 
     /// Insert a paste.
     pub async fn insert_paste(
-        &mut self,
+        &self,
         key: &str,
-        delete_key: &str,
+        delete_key_hash: &str,
         file_name: &str,
+        object: &Object,
+        content_hash: Option<&str>,
+        secret_hash: Option<&str>,
+        token: Option<&ApiToken>,
     ) -> crate::AppResult<Paste> {
-        let mut conn = self.pool.acquire().await?;
-        let paste = sqlx::query_as::<_, Paste>(
-            "INSERT INTO paste (key, delete_key, file_name) VALUES (?, ?, ?) RETURNING key, \
-             delete_key, file_name, timestamp",
-        )
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.sql("DELETE FROM deleted_paste WHERE key = ?"))
+            .bind(key)
+            .execute(&mut tx)
+            .await?;
+        let paste = sqlx::query_as::<_, Paste>(&self.sql(
+            "INSERT INTO paste (key, delete_key_hash, file_name, object_hash, content_hash, \
+             encoding, key_id, secret_hash, tier, token_name, owner) VALUES (?, ?, ?, ?, ?, ?, ?, \
+             ?, ?, ?, ?) RETURNING key, delete_key_hash, file_name, timestamp, object_hash, \
+             content_hash, encoding, key_id, secret_hash, tier, token_name, owner, expires_at",
+        ))
         .bind(key)
-        .bind(delete_key)
+        .bind(delete_key_hash)
         .bind(file_name)
-        .fetch_one(&mut conn)
+        .bind(&object.hash)
+        .bind(content_hash)
+        .bind(&object.encoding)
+        .bind(&object.key_id)
+        .bind(secret_hash)
+        .bind(&object.tier)
+        .bind(token.map(|token| &token.name))
+        .bind(token.map(|token| &token.owner))
+        .fetch_one(&mut tx)
         .await?;
+        tx.commit().await?;
         Ok(paste)
     }
 
-    /// Delete a paste by key.
-    pub async fn delete_paste(&mut self, key: &str) -> crate::AppResult<()> {
+    /// Insert a paste along with its key, delete key hash and timestamp, as when
+    /// restoring it from a backup.
+    pub async fn restore_paste(&self, paste: &Paste) -> crate::AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.sql("DELETE FROM deleted_paste WHERE key = ?"))
+            .bind(&paste.key)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&self.sql(
+            "INSERT INTO paste (key, delete_key_hash, file_name, timestamp, object_hash, \
+             content_hash, encoding, key_id, secret_hash, tier, token_name, owner, expires_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        ))
+        .bind(&paste.key)
+        .bind(&paste.delete_key_hash)
+        .bind(&paste.file_name)
+        .bind(paste.timestamp)
+        .bind(&paste.object_hash)
+        .bind(&paste.content_hash)
+        .bind(&paste.encoding)
+        .bind(&paste.key_id)
+        .bind(&paste.secret_hash)
+        .bind(&paste.tier)
+        .bind(&paste.token_name)
+        .bind(&paste.owner)
+        .bind(paste.expires_at)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the pastes uploaded by an owner, newest first.
+    pub async fn get_pastes_by_owner(&self, owner: &str) -> crate::AppResult<Vec<Paste>> {
         let mut conn = self.pool.acquire().await?;
-        sqlx::query("DELETE FROM paste WHERE key = ?")
+        let pastes = sqlx::query_as::<_, Paste>(
+            &self.sql("SELECT * FROM paste WHERE owner = ? ORDER BY timestamp DESC, key"),
+        )
+        .bind(owner)
+        .fetch_all(&mut conn)
+        .await?;
+        Ok(pastes)
+    }
+
+    /// Set the time a paste expires at, overriding the usual expiration.
+    pub async fn set_expires_at(
+        &self,
+        key: &str,
+        expires_at: DateTime<Utc>,
+    ) -> crate::AppResult<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(&self.sql("UPDATE paste SET expires_at = ? WHERE key = ?"))
+            .bind(expires_at)
             .bind(key)
             .execute(&mut conn)
             .await?;
         Ok(())
     }
+
+    /// Delete a paste by key, remembering that it was deleted.
+    pub async fn delete_paste(&self, key: &str) -> crate::AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.sql("DELETE FROM paste WHERE key = ?"))
+            .bind(key)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&self.sql("DELETE FROM deleted_paste WHERE key = ?"))
+            .bind(key)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&self.sql("INSERT INTO deleted_paste (key, timestamp) VALUES (?, ?)"))
+            .bind(key)
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the pastes with the given content hash.
+    pub async fn get_pastes_by_content_hash(&self, hash: &str) -> crate::AppResult<Vec<Paste>> {
+        let mut conn = self.pool.acquire().await?;
+        let pastes =
+            sqlx::query_as::<_, Paste>(&self.sql("SELECT * FROM paste WHERE content_hash = ?"))
+                .bind(hash)
+                .fetch_all(&mut conn)
+                .await?;
+        Ok(pastes)
+    }
+
+    /// Forget about pastes deleted before the given time, returning how many
+    /// were forgotten.
+    pub async fn delete_deleted_pastes_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> crate::AppResult<u64> {
+        let mut conn = self.pool.acquire().await?;
+        let count = sqlx::query(&self.sql("DELETE FROM deleted_paste WHERE timestamp < ?"))
+            .bind(before)
+            .execute(&mut conn)
+            .await?
+            .rows_affected();
+        Ok(count)
+    }
+
+    /// Check whether a paste with the given key was deleted.
+    pub async fn is_paste_deleted(&self, key: &str) -> crate::AppResult<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let deleted = sqlx::query(&self.sql("SELECT key FROM deleted_paste WHERE key = ?"))
+            .bind(key)
+            .fetch_optional(&mut conn)
+            .await?
+            .is_some();
+        Ok(deleted)
+    }
+
+    /// Block uploads of content with the given hash.
+    pub async fn insert_blocked_hash(&self, hash: &str) -> crate::AppResult<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(
+            &self.sql("INSERT INTO blocked_hash (hash) VALUES (?) ON CONFLICT (hash) DO NOTHING"),
+        )
+        .bind(hash)
+        .execute(&mut conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Check whether uploads of content with the given hash are blocked.
+    pub async fn is_hash_blocked(&self, hash: &str) -> crate::AppResult<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let blocked = sqlx::query(&self.sql("SELECT hash FROM blocked_hash WHERE hash = ?"))
+            .bind(hash)
+            .fetch_optional(&mut conn)
+            .await?
+            .is_some();
+        Ok(blocked)
+    }
+
+    /// Get an API token by the hash of the token.
+    pub async fn get_token(&self, token_hash: &str) -> crate::AppResult<ApiToken> {
+        let mut conn = self.pool.acquire().await?;
+        let token = sqlx::query_as::<_, ApiToken>(
+            &self.sql("SELECT name, owner FROM api_token WHERE token_hash = ?"),
+        )
+        .bind(token_hash)
+        .fetch_one(&mut conn)
+        .await?;
+        Ok(token)
+    }
+
+    /// Insert an API token by name, owner and the hash of the token.
+    pub async fn insert_token(
+        &self,
+        name: &str,
+        owner: &str,
+        token_hash: &str,
+    ) -> crate::AppResult<()> {
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query(&self.sql("INSERT INTO api_token (name, owner, token_hash) VALUES (?, ?, ?)"))
+            .bind(name)
+            .bind(owner)
+            .bind(token_hash)
+            .execute(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete an API token by name, returning whether it existed.
+    pub async fn delete_token(&self, name: &str) -> crate::AppResult<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let deleted = sqlx::query(&self.sql("DELETE FROM api_token WHERE name = ?"))
+            .bind(name)
+            .execute(&mut conn)
+            .await?
+            .rows_affected();
+        Ok(deleted > 0)
+    }
+
+    /// Record an upload for counting against quotas, forgetting uploads from
+    /// before the given time.
+    pub async fn insert_upload(
+        &self,
+        client_ip: &str,
+        token_name: Option<&str>,
+        size: u64,
+        forget_before: DateTime<Utc>,
+    ) -> crate::AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.sql(
+            "INSERT INTO upload (client_ip, token_name, size, timestamp) VALUES (?, ?, ?, ?)",
+        ))
+        .bind(client_ip)
+        .bind(token_name)
+        .bind(size as i64)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(&self.sql("DELETE FROM upload WHERE timestamp < ?"))
+            .bind(forget_before)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the uploads made from a client IP since the given time.
+    pub async fn get_upload_usage_by_ip(
+        &self,
+        client_ip: &str,
+        since: DateTime<Utc>,
+    ) -> crate::AppResult<UploadUsage> {
+        self.get_upload_usage("client_ip", client_ip, since).await
+    }
+
+    /// Get the uploads made with an API token since the given time.
+    pub async fn get_upload_usage_by_token(
+        &self,
+        token_name: &str,
+        since: DateTime<Utc>,
+    ) -> crate::AppResult<UploadUsage> {
+        self.get_upload_usage("token_name", token_name, since).await
+    }
+
+    /// Add a reference to a stored object by hash, creating it if it is not
+    /// yet referenced.
+    ///
+    /// The size, encoding and key ID are only used for new objects. If the
+    /// returned object has a single reference, it was newly created.
+    pub async fn acquire_object(
+        &self,
+        hash: &str,
+        size: usize,
+        encoding: Option<&str>,
+        key_id: Option<&str>,
+    ) -> crate::AppResult<Object> {
+        // a single statement, so that concurrent first uploads of the same
+        // data don't both try to create the object
+        let mut conn = self.pool.acquire().await?;
+        let object = sqlx::query_as::<_, Object>(&self.sql(
+            "INSERT INTO object (hash, size, ref_count, encoding, key_id) VALUES (?, ?, 1, ?, ?) \
+             ON CONFLICT (hash) DO UPDATE SET ref_count = object.ref_count + 1 RETURNING hash, \
+             size, ref_count, encoding, key_id, tier",
+        ))
+        .bind(hash)
+        .bind(size as i64)
+        .bind(encoding)
+        .bind(key_id)
+        .fetch_one(&mut conn)
+        .await?;
+        Ok(object)
+    }
+
+    /// Remove a number of references to a stored object by hash, deleting its
+    /// data with `delete_data` once it is no longer referenced.
+    ///
+    /// The data is deleted before the object's row is, while the row is still
+    /// locked, so that a concurrent upload of the same data waits to create
+    /// the object anew instead of having its data deleted from under it.
+    pub async fn release_object<F, Fut>(
+        &self,
+        hash: &str,
+        count: usize,
+        delete_data: F,
+    ) -> crate::AppResult<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = crate::AppResult<()>>,
+    {
+        let mut tx = self.pool.begin().await?;
+        let ref_count =
+            sqlx::query_scalar::<_, i64>(&self.sql(
+                "UPDATE object SET ref_count = ref_count - ? WHERE hash = ? RETURNING ref_count",
+            ))
+            .bind(count as i64)
+            .bind(hash)
+            .fetch_optional(&mut tx)
+            .await?;
+
+        if matches!(ref_count, Some(ref_count) if ref_count <= 0) {
+            delete_data().await?;
+            sqlx::query(&self.sql("DELETE FROM object WHERE hash = ?"))
+                .bind(hash)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record the storage tier of an object and the pastes which refer to it.
+    pub async fn set_tier(&self, object_key: &str, tier: &str) -> crate::AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&self.sql("UPDATE object SET tier = ? WHERE hash = ?"))
+            .bind(tier)
+            .bind(object_key)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&self.sql(
+            "UPDATE paste SET tier = ? WHERE object_hash = ? OR (object_hash IS NULL AND key = ?)",
+        ))
+        .bind(tier)
+        .bind(object_key)
+        .bind(object_key)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the total size of all stored objects in bytes.
+    ///
+    /// Pastes created before deduplication are not counted.
+    pub async fn get_storage_usage(&self) -> crate::AppResult<u64> {
+        let mut conn = self.pool.acquire().await?;
+        let usage = sqlx::query_scalar::<_, i64>(
+            &self.sql("SELECT CAST(COALESCE(SUM(size), 0) AS BIGINT) FROM object"),
+        )
+        .fetch_one(&mut conn)
+        .await?;
+        Ok(usage as u64)
+    }
+
+    /// Adapt a query written with `?` placeholders to the database.
+    fn sql<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        if !self.numbered_placeholders {
+            return Cow::Borrowed(query);
+        }
+        Cow::Owned(number_placeholders(query))
+    }
+
+    async fn get_upload_usage(
+        &self,
+        column: &str,
+        value: &str,
+        since: DateTime<Utc>,
+    ) -> crate::AppResult<UploadUsage> {
+        let mut conn = self.pool.acquire().await?;
+        let usage = sqlx::query_as::<_, UploadUsage>(&self.sql(&format!(
+            "SELECT COUNT(*) AS count, CAST(COALESCE(SUM(size), 0) AS BIGINT) AS size, \
+             MIN(timestamp) AS oldest FROM upload WHERE {column} = ? AND timestamp >= ?"
+        )))
+        .bind(value)
+        .bind(since)
+        .fetch_one(&mut conn)
+        .await?;
+        Ok(usage)
+    }
+}
+
+/// Replace `?` placeholders with numbered ones (`$1`, `$2`, ...), as used by
+/// Postgres.
+///
+/// Queries must not contain a literal `?` anywhere else.
+fn number_placeholders(query: &str) -> String {
+    let mut parts = query.split('?');
+    let mut numbered = parts.next().unwrap_or_default().to_owned();
+    for (index, part) in parts.enumerate() {
+        numbered.push_str(&format!("${}{part}", index + 1));
+    }
+    numbered
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn number_placeholders_in_order() {
+        assert_eq!(
+            number_placeholders("SELECT * FROM paste WHERE key = ? AND owner = ?"),
+            "SELECT * FROM paste WHERE key = $1 AND owner = $2"
+        );
+        assert_eq!(
+            number_placeholders("INSERT INTO t (a, b, c) VALUES (?, ?, ?)"),
+            "INSERT INTO t (a, b, c) VALUES ($1, $2, $3)"
+        );
+    }
+
+    #[test]
+    fn number_placeholders_at_ends() {
+        assert_eq!(number_placeholders("?"), "$1");
+        assert_eq!(number_placeholders("? = ?"), "$1 = $2");
+        assert_eq!(number_placeholders("SELECT 1"), "SELECT 1");
+        assert_eq!(number_placeholders(""), "");
+    }
+
+    #[test]
+    fn number_placeholders_past_nine() {
+        let query = vec!["?"; 11].join(", ");
+        assert_eq!(
+            number_placeholders(&query),
+            "$1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11"
+        );
+    }
+
+    fn test_config(url: &str) -> config::Database {
+        config::Database {
+            url: url.to_owned(),
+            // every connection to an in-memory SQLite database is its own
+            max_connections: Some(1),
+            min_connections: None,
+            acquire_timeout_secs: None,
+            idle_timeout_secs: None,
+            sqlite_wal: None,
+            sqlite_busy_timeout_ms: None,
+        }
+    }
+
+    /// Run queries which differ between databases against a database.
+    async fn check_queries(database: &Database) {
+        let hash = format!("test-{}", Uuid::new_v4());
+        let object = database.acquire_object(&hash, 3, None, None).await.unwrap();
+        assert_eq!(object.ref_count, 1);
+        let object = database.acquire_object(&hash, 3, None, None).await.unwrap();
+        assert_eq!(object.ref_count, 2);
+
+        let key = format!("test-{}", Uuid::new_v4());
+        database
+            .insert_paste(
+                &key,
+                "salt$hash",
+                "test.txt",
+                &object,
+                Some(&hash),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let paste = database.get_paste(&key).await.unwrap();
+        assert_eq!(paste.object_hash.as_deref(), Some(hash.as_str()));
+        let pastes = database.get_pastes_by_content_hash(&hash).await.unwrap();
+        assert_eq!(pastes.len(), 1);
+
+        database.delete_paste(&key).await.unwrap();
+        assert!(database.is_paste_deleted(&key).await.unwrap());
+
+        let deleted = Cell::new(false);
+        database
+            .release_object(&hash, 2, || async {
+                deleted.set(true);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert!(deleted.get());
+
+        let client_ip = format!("test-{}", Uuid::new_v4());
+        let since = Utc::now() - Duration::minutes(1);
+        database
+            .insert_upload(&client_ip, None, 5, since - Duration::days(1))
+            .await
+            .unwrap();
+        let usage = database
+            .get_upload_usage_by_ip(&client_ip, since)
+            .await
+            .unwrap();
+        assert_eq!((usage.count, usage.size), (1, 5));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_queries() {
+        let database = Database::connect(&test_config("sqlite::memory:"))
+            .await
+            .unwrap();
+        assert!(!database.numbered_placeholders);
+        check_queries(&database).await;
+    }
+
+    /// Run against a scratch Postgres database with
+    /// `NONBIN_TEST_POSTGRES_URL=postgres://... cargo test --features postgres -- --ignored`.
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    #[ignore = "needs a Postgres database in NONBIN_TEST_POSTGRES_URL"]
+    async fn postgres_queries() {
+        let url = std::env::var("NONBIN_TEST_POSTGRES_URL").unwrap();
+        let database = Database::connect(&test_config(&url)).await.unwrap();
+        assert!(database.numbered_placeholders);
+        check_queries(&database).await;
+    }
 }
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use bytes::{Bytes, BytesMut};
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::XChaCha20Poly1305;
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, Rng};
+use sha2::Sha256;
+use tokio::fs;
+
+use crate::error::AppError;
+use crate::{config, AppResult};
+
+pub use chacha20poly1305::Key;
+
+/// The size of the plaintext in each encrypted chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The size of the authentication tag appended to each encrypted chunk.
+const TAG_SIZE: usize = 16;
+
+/// The size of the random nonce prefix at the start of encrypted data.
+///
+/// XChaCha20-Poly1305 nonces are 24 bytes, 5 of which are used by the STREAM
+/// construction for the chunk counter and last chunk flag.
+const NONCE_SIZE: usize = 19;
+
+/// The set of keys that data can be encrypted with, identified by key ID.
+#[derive(Clone)]
+pub struct KeyRing {
+    current_key_id: String,
+    keys: Arc<HashMap<String, Key>>,
+}
+
+impl KeyRing {
+    pub async fn load(config: &config::Encryption) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+
+        for key_config in &config.keys {
+            let encoded = match (&key_config.key, &key_config.key_file) {
+                (Some(key), None) => key.clone(),
+                (None, Some(key_file)) => fs::read_to_string(key_file)
+                    .await
+                    .with_context(|| format!("failed to read key file {key_file:?}"))?,
+                _ => bail!(
+                    "key '{}' needs exactly one of key or key_file",
+                    key_config.id
+                ),
+            };
+
+            let key = hex::decode(encoded.trim())
+                .with_context(|| format!("key '{}' is not valid hex", key_config.id))?;
+            if key.len() != 32 {
+                bail!("key '{}' must be 32 bytes long", key_config.id);
+            }
+
+            keys.insert(key_config.id.clone(), *Key::from_slice(&key));
+        }
+
+        if !keys.contains_key(&config.current_key_id) {
+            bail!("current key '{}' is not configured", config.current_key_id);
+        }
+
+        Ok(KeyRing {
+            current_key_id: config.current_key_id.clone(),
+            keys: Arc::new(keys),
+        })
+    }
+
+    /// Get the ID of the key that new data should be encrypted with.
+    pub fn current_key_id(&self) -> &str {
+        &self.current_key_id
+    }
+
+    /// Get a key by ID.
+    pub fn get(&self, key_id: &str) -> AppResult<&Key> {
+        self.keys
+            .get(key_id)
+            .ok_or_else(|| AppError::UnknownEncryptionKey {
+                key_id: key_id.to_owned(),
+            })
+    }
+
+    /// Get the name to store an object under from the hash of its content,
+    /// keyed by a key so that the name reveals nothing without it.
+    pub fn object_name(&self, key_id: &str, content_hash: &[u8]) -> AppResult<String> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.get(key_id)?).expect("HMAC takes keys of any size");
+        // keep the key's use here apart from its use for encryption
+        mac.update(b"nonbin object name\0");
+        mac.update(content_hash);
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+/// Encrypt a stream of data.
+///
+/// The output starts with a random nonce prefix, followed by the data split
+/// into chunks which are each authenticated, so that truncation or reordering
+/// is detected when decrypting.
+pub fn encrypt<S>(key: &Key, data: S) -> impl Stream<Item = AppResult<Bytes>> + Unpin
+where
+    S: Stream<Item = AppResult<Bytes>> + Unpin,
+{
+    let mut nonce = [0u8; NONCE_SIZE];
+    thread_rng().fill(&mut nonce);
+
+    let encryptor = EncryptorBE32::<XChaCha20Poly1305>::new(key, GenericArray::from_slice(&nonce));
+
+    let chunks = stream::try_unfold(
+        Some((data, BytesMut::new(), encryptor)),
+        |state| async move {
+            let Some((mut data, mut buffer, mut encryptor)) = state else {
+                return Ok::<_, AppError>(None);
+            };
+
+            loop {
+                // only full chunks are written until the end is known, since
+                // the last chunk is encrypted differently
+                if buffer.len() > CHUNK_SIZE {
+                    let chunk = buffer.split_to(CHUNK_SIZE);
+                    let ciphertext = encryptor
+                        .encrypt_next(chunk.as_ref())
+                        .map_err(|_| AppError::Encryption)?;
+                    return Ok(Some((ciphertext.into(), Some((data, buffer, encryptor)))));
+                }
+
+                match data.try_next().await? {
+                    Some(chunk) => buffer.extend_from_slice(&chunk),
+                    None => {
+                        let ciphertext = encryptor
+                            .encrypt_last(buffer.as_ref())
+                            .map_err(|_| AppError::Encryption)?;
+                        return Ok(Some((ciphertext.into(), None)));
+                    }
+                }
+            }
+        },
+    );
+
+    Box::pin(stream::once(async move { Ok(Bytes::copy_from_slice(&nonce)) }).chain(chunks))
+}
+
+/// Decrypt a stream of data produced by [`encrypt`].
+pub fn decrypt<S>(key: &Key, data: S) -> impl Stream<Item = AppResult<Bytes>>
+where
+    S: Stream<Item = AppResult<Bytes>>,
+{
+    let key = *key;
+
+    stream::try_unfold(
+        Some((
+            Box::pin(data),
+            BytesMut::new(),
+            None::<DecryptorBE32<XChaCha20Poly1305>>,
+        )),
+        move |state| async move {
+            let Some((mut data, mut buffer, mut decryptor)) = state else {
+                return Ok::<_, AppError>(None);
+            };
+
+            loop {
+                if decryptor.is_none() && buffer.len() >= NONCE_SIZE {
+                    let nonce = buffer.split_to(NONCE_SIZE);
+                    decryptor = Some(DecryptorBE32::<XChaCha20Poly1305>::new(
+                        &key,
+                        GenericArray::from_slice(&nonce),
+                    ));
+                }
+
+                // hold back the last chunk, which is decrypted differently
+                if let Some(inner) = decryptor.as_mut() {
+                    if buffer.len() > CHUNK_SIZE + TAG_SIZE {
+                        let chunk = buffer.split_to(CHUNK_SIZE + TAG_SIZE);
+                        let plaintext = inner
+                            .decrypt_next(chunk.as_ref())
+                            .map_err(|_| AppError::Encryption)?;
+                        return Ok(Some((plaintext.into(), Some((data, buffer, decryptor)))));
+                    }
+                }
+
+                match data.try_next().await? {
+                    Some(chunk) => buffer.extend_from_slice(&chunk),
+                    None => {
+                        let decryptor = decryptor.ok_or(AppError::Encryption)?;
+                        let plaintext = decryptor
+                            .decrypt_last(buffer.as_ref())
+                            .map_err(|_| AppError::Encryption)?;
+                        return Ok(Some((plaintext.into(), None)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> Key {
+        *Key::from_slice(&[7; 32])
+    }
+
+    /// Split data into a stream of chunks of a given size.
+    fn chunked(data: &[u8], size: usize) -> impl Stream<Item = AppResult<Bytes>> + Unpin {
+        let chunks = data
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        stream::iter(chunks)
+    }
+
+    async fn collect(data: impl Stream<Item = AppResult<Bytes>>) -> AppResult<Vec<u8>> {
+        let chunks = data.try_collect::<Vec<_>>().await?;
+        Ok(chunks.concat())
+    }
+
+    async fn encrypt_all(plaintext: &[u8], size: usize) -> Vec<u8> {
+        collect(encrypt(&test_key(), chunked(plaintext, size.max(1))))
+            .await
+            .unwrap()
+    }
+
+    async fn decrypt_all(ciphertext: &[u8], size: usize) -> AppResult<Vec<u8>> {
+        collect(decrypt(&test_key(), chunked(ciphertext, size.max(1)))).await
+    }
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let lengths = [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE,
+            2 * CHUNK_SIZE + 10,
+        ];
+        for len in lengths {
+            let plaintext = plaintext(len);
+            // both whole and in pieces which don't line up with the chunks
+            for size in [len, 1000] {
+                let ciphertext = encrypt_all(&plaintext, size).await;
+                // the last chunk may be empty, but is always there
+                let chunks = len.saturating_sub(1) / CHUNK_SIZE + 1;
+                assert_eq!(ciphertext.len(), NONCE_SIZE + len + chunks * TAG_SIZE);
+
+                for size in [ciphertext.len(), 1000, CHUNK_SIZE + TAG_SIZE] {
+                    assert_eq!(
+                        decrypt_all(&ciphertext, size).await.unwrap(),
+                        plaintext,
+                        "length {len}, read in pieces of {size}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn nonces_differ() {
+        let plaintext = plaintext(100);
+        assert_ne!(
+            encrypt_all(&plaintext, 100).await,
+            encrypt_all(&plaintext, 100).await
+        );
+    }
+
+    #[tokio::test]
+    async fn truncated() {
+        let ciphertext = encrypt_all(&plaintext(2 * CHUNK_SIZE + 10), 1000).await;
+
+        let lengths = [
+            0,
+            NONCE_SIZE - 1,
+            NONCE_SIZE,
+            // at the end of a chunk which isn't the last
+            NONCE_SIZE + CHUNK_SIZE + TAG_SIZE,
+            NONCE_SIZE + 2 * (CHUNK_SIZE + TAG_SIZE),
+            ciphertext.len() - 1,
+        ];
+        for len in lengths {
+            assert!(
+                matches!(
+                    decrypt_all(&ciphertext[..len], 1000).await,
+                    Err(AppError::Encryption)
+                ),
+                "truncated to {len}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered() {
+        let ciphertext = encrypt_all(&plaintext(CHUNK_SIZE + 1), 1000).await;
+
+        for index in [0, NONCE_SIZE, NONCE_SIZE + CHUNK_SIZE, ciphertext.len() - 1] {
+            let mut tampered = ciphertext.clone();
+            tampered[index] ^= 1;
+            assert!(
+                matches!(
+                    decrypt_all(&tampered, 1000).await,
+                    Err(AppError::Encryption)
+                ),
+                "tampered at {index}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn wrong_key() {
+        let ciphertext = encrypt_all(&plaintext(100), 100).await;
+        let result = collect(decrypt(
+            Key::from_slice(&[8; 32]),
+            chunked(&ciphertext, 1000),
+        ))
+        .await;
+        assert!(matches!(result, Err(AppError::Encryption)));
+    }
+
+    #[test]
+    fn object_names() {
+        let key_ring = KeyRing {
+            current_key_id: "a".to_owned(),
+            keys: Arc::new(HashMap::from([
+                ("a".to_owned(), test_key()),
+                ("b".to_owned(), *Key::from_slice(&[8; 32])),
+            ])),
+        };
+        let content_hash = [1; 32];
+
+        let name = key_ring.object_name("a", &content_hash).unwrap();
+        assert_eq!(name, key_ring.object_name("a", &content_hash).unwrap());
+        assert_eq!(name.len(), 64);
+        assert_ne!(name, hex::encode(content_hash));
+        assert_ne!(name, key_ring.object_name("a", &[2; 32]).unwrap());
+        assert_ne!(name, key_ring.object_name("b", &content_hash).unwrap());
+        assert!(matches!(
+            key_ring.object_name("c", &content_hash),
+            Err(AppError::UnknownEncryptionKey { .. })
+        ));
+    }
+}
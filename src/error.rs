@@ -1,5 +1,5 @@
 use axum::extract::multipart::MultipartError;
-use axum::http::{self, StatusCode};
+use axum::http::{self, header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 use tracing::warn;
@@ -16,6 +16,8 @@ pub type AppResult<T> = std::result::Result<T, AppError>;
 pub enum AppError {
     #[error("not found")]
     NotFound,
+    #[error("paste was deleted or has expired")]
+    Gone,
     #[error("insufficient storage")]
     InsufficientStorage,
     #[error("missing multipart file")]
@@ -28,6 +30,28 @@ pub enum AppError {
     MissingDeleteKey,
     #[error("wrong delete key")]
     WrongDeleteKey,
+    #[error("missing API token")]
+    MissingToken,
+    #[error("invalid API token")]
+    InvalidToken,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("your address is not allowed to {action}")]
+    Blocked { action: &'static str },
+    #[error("content is blocked")]
+    BlockedContent,
+    #[error("upload quota exceeded, retry after {retry_after_secs} seconds")]
+    QuotaExceeded { retry_after_secs: u64 },
+    #[error("rate limited, retry after {retry_after_secs} seconds")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("missing secret for private paste")]
+    MissingSecret,
+    #[error("wrong secret for private paste")]
+    WrongSecret,
+    #[error("unknown encryption key '{key_id}'")]
+    UnknownEncryptionKey { key_id: String },
+    #[error("failed to encrypt or decrypt data")]
+    Encryption,
     #[error("error reading multipart data")]
     Multipart {
         #[from]
@@ -53,12 +77,24 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status_code = match &self {
             AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Gone => StatusCode::GONE,
             AppError::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
             AppError::MissingFile => StatusCode::BAD_REQUEST,
             AppError::MissingFileName => StatusCode::BAD_REQUEST,
             AppError::MissingFileContentType => StatusCode::BAD_REQUEST,
             AppError::MissingDeleteKey => StatusCode::BAD_REQUEST,
             AppError::WrongDeleteKey => StatusCode::UNAUTHORIZED,
+            AppError::MissingToken => StatusCode::UNAUTHORIZED,
+            AppError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::Blocked { .. } => StatusCode::FORBIDDEN,
+            AppError::BlockedContent => StatusCode::FORBIDDEN,
+            AppError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::MissingSecret => StatusCode::BAD_REQUEST,
+            AppError::WrongSecret => StatusCode::UNAUTHORIZED,
+            AppError::UnknownEncryptionKey { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Encryption => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Multipart { .. } => StatusCode::BAD_REQUEST,
             AppError::Http { .. } => StatusCode::BAD_REQUEST,
             AppError::Database { .. } => StatusCode::INTERNAL_SERVER_ERROR,
@@ -69,12 +105,33 @@ impl IntoResponse for AppError {
 
         warn!("error during request: {self}");
 
-        let reason = status_code
-            .canonical_reason()
-            .unwrap_or("unknown error")
-            .to_lowercase();
+        let reason = match &self {
+            // blocked clients should know why, and that retrying won't help
+            AppError::Blocked { .. } => self.to_string(),
+            _ => status_code
+                .canonical_reason()
+                .unwrap_or("unknown error")
+                .to_lowercase(),
+        };
+
+        let mut response = (status_code, reason).into_response();
+        if let AppError::QuotaExceeded { retry_after_secs }
+        | AppError::RateLimited { retry_after_secs } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
+    }
+}
 
-        (status_code, reason).into_response()
+#[cfg(feature = "s3")]
+impl From<SdkError<s3::error::CopyObjectError>> for AppError {
+    fn from(source: SdkError<s3::error::CopyObjectError>) -> Self {
+        AppError::S3 {
+            source: Box::new(source),
+        }
     }
 }
 
@@ -113,6 +170,15 @@ impl From<SdkError<s3::error::HeadObjectError>> for AppError {
     }
 }
 
+#[cfg(feature = "s3")]
+impl From<SdkError<s3::error::ListObjectsV2Error>> for AppError {
+    fn from(source: SdkError<s3::error::ListObjectsV2Error>) -> Self {
+        AppError::S3 {
+            source: Box::new(source),
+        }
+    }
+}
+
 #[cfg(feature = "s3")]
 impl From<SdkError<s3::error::PutObjectError>> for AppError {
     fn from(source: SdkError<s3::error::PutObjectError>) -> Self {
@@ -133,6 +199,11 @@ impl From<sqlx::Error> for AppError {
 
 impl From<std::io::Error> for AppError {
     fn from(source: std::io::Error) -> Self {
+        // recover errors that were wrapped to pass through readers
+        if source.get_ref().map_or(false, |e| e.is::<AppError>()) {
+            return *source.into_inner().unwrap().downcast().unwrap();
+        }
+
         match source.kind() {
             std::io::ErrorKind::NotFound => AppError::NotFound,
             std::io::ErrorKind::StorageFull => AppError::InsufficientStorage,
@@ -6,24 +6,31 @@
 use std::path::PathBuf;
 
 use anyhow::{bail, Context};
-use axum::extract::FromRef;
 use clap::{Parser, Subcommand};
 use directories_next::ProjectDirs;
-use tokio::fs;
 use tracing::info;
 
-use crate::config::Config;
+use crate::access::AccessControl;
+use crate::config::{Config, StorageKind};
 use crate::db::Database;
+use crate::encryption::KeyRing;
+use crate::rate_limit::RateLimiters;
 use crate::storage::AnyStorage;
 use crate::words::WordLists;
 
+mod access;
+mod archive;
 mod commands;
+mod compression;
 mod config;
 mod controllers;
 mod db;
+mod encryption;
 mod error;
 mod markdown;
 mod models;
+mod proxy;
+mod rate_limit;
 mod storage;
 mod words;
 
@@ -42,15 +49,76 @@ struct Args {
 
 #[derive(Debug, Subcommand)]
 enum Command {
+    ApplyTiering,
+    BlockPaste {
+        /// The key of the paste to delete and block the content of
+        key: String,
+
+        /// The secret of the paste, if it is private
+        ///
+        /// The content of a private paste can't be hashed without it.
+        #[arg(long)]
+        secret: Option<String>,
+    },
+    Check {
+        /// Also read every object and verify its hash
+        #[arg(long)]
+        verify: bool,
+
+        /// Delete pastes with missing objects and objects without pastes
+        ///
+        /// Uploads in progress look like orphaned objects, so the service should
+        /// not be running at the same time.
+        #[arg(long)]
+        repair: bool,
+    },
+    CreateToken {
+        /// A name identifying the token
+        name: String,
+
+        /// The account the token belongs to, which defaults to its name
+        ///
+        /// Tokens with the same owner can see and manage each other's pastes.
+        #[arg(long)]
+        owner: Option<String>,
+    },
+    Export {
+        /// The archive to write pastes to
+        path: PathBuf,
+    },
+    Import {
+        /// The archive to read pastes from
+        path: PathBuf,
+    },
+    MigrateStorage {
+        /// The storage backend to copy pastes from
+        #[arg(long)]
+        from: StorageKind,
+
+        /// The storage backend to copy pastes to
+        #[arg(long)]
+        to: StorageKind,
+
+        /// Delete each object from the source once it has been copied
+        #[arg(long)]
+        delete_source: bool,
+    },
     PurgeExpired,
+    RevokeToken {
+        /// The name of the token to revoke
+        name: String,
+    },
     Serve,
 }
 
-#[derive(Clone, FromRef)]
+#[derive(Clone)]
 pub struct App {
     config: Config,
     database: Database,
     storage: AnyStorage,
+    key_ring: Option<KeyRing>,
+    access: AccessControl,
+    rate_limiters: RateLimiters,
     word_lists: WordLists,
 }
 
@@ -60,47 +128,39 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Args::parse();
 
-    let config: Config = {
-        let path = if let Some(path) = args.config {
-            path
-        } else {
-            let options = vec![
-                Some(PathBuf::from("config.toml")),
-                ProjectDirs::from("in", "nonb", "nonbin")
-                    .map(|p| p.config_dir().join("config.toml")),
-            ];
-
-            match options.into_iter().flatten().find(|p| p.exists()) {
-                Some(path) => path,
-                _ => bail!("could not locate config.toml"),
-            }
-        };
-        info!("reading config from {path:?}");
-        let contents = fs::read_to_string(path)
-            .await
-            .context("failed to read config file")?;
-        toml::from_str(&contents).context("failed to parse config file")?
+    let config_path = if let Some(path) = args.config {
+        path
+    } else {
+        let options = vec![
+            Some(PathBuf::from("config.toml")),
+            ProjectDirs::from("in", "nonb", "nonbin").map(|p| p.config_dir().join("config.toml")),
+        ];
+
+        match options.into_iter().flatten().find(|p| p.exists()) {
+            Some(path) => path,
+            _ => bail!("could not locate config.toml"),
+        }
     };
+    info!("reading config from {config_path:?}");
+    let config = Config::load(&config_path).await?;
 
-    let database = Database::connect(&config.database.url).await?;
-
-    let storage = match &config.storage.kind {
-        config::StorageKind::File => storage::file::FileStorage::new(&config.storage.file.dir)
-            .await?
-            .into(),
-        #[cfg(feature = "s3")]
-        config::StorageKind::S3 => {
-            let s3_config = &config.storage.s3;
-            storage::s3::S3Storage::new(
-                &s3_config.bucket,
-                s3_config.region.as_deref(),
-                s3_config.endpoint.as_deref(),
-            )
-            .await
-            .into()
-        }
+    let database = Database::connect(&config.database).await?;
+
+    let storage = AnyStorage::from_config(&config.storage, config.storage.kind).await?;
+
+    let key_ring = match &config.encryption {
+        Some(encryption_config) => Some(
+            KeyRing::load(encryption_config)
+                .await
+                .context("failed to load encryption keys")?,
+        ),
+        None => None,
     };
 
+    let access = AccessControl::new(config.access.as_ref());
+
+    let rate_limiters = RateLimiters::new(config.limits.rate_limits.as_ref());
+
     let word_lists = WordLists::load(
         &config.word_lists.adjectives_file,
         &config.word_lists.nouns_file,
@@ -111,12 +171,31 @@ async fn main() -> anyhow::Result<()> {
         config,
         database,
         storage,
+        key_ring,
+        access,
+        rate_limiters,
         word_lists,
     };
 
     match &args.command {
+        Command::ApplyTiering => commands::apply_tiering::run(app).await?,
+        Command::BlockPaste { key, secret } => {
+            commands::block_paste::run(app, key, secret.as_deref()).await?
+        }
+        Command::Check { verify, repair } => commands::check::run(app, *verify, *repair).await?,
+        Command::CreateToken { name, owner } => {
+            commands::create_token::run(app, name, owner.as_deref()).await?
+        }
+        Command::Export { path } => commands::export::run(app, path).await?,
+        Command::Import { path } => commands::import::run(app, path).await?,
+        Command::MigrateStorage {
+            from,
+            to,
+            delete_source,
+        } => commands::migrate_storage::run(app, *from, *to, *delete_source).await?,
         Command::PurgeExpired => commands::purge_expired::run(app).await?,
-        Command::Serve => commands::serve::run(app).await?,
+        Command::RevokeToken { name } => commands::revoke_token::run(app, name).await?,
+        Command::Serve => commands::serve::run(app, &config_path).await?,
     }
 
     Ok(())
@@ -1,11 +1,105 @@
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
 use sqlx::FromRow;
+use subtle::ConstantTimeEq;
 
-#[derive(FromRow, Serialize)]
+use crate::compression::Encoding;
+use crate::storage::tiered::Tier;
+
+#[derive(FromRow)]
 pub struct Paste {
     pub key: String,
-    pub delete_key: Option<String>,
+    pub delete_key_hash: Option<String>,
     pub file_name: String,
     pub timestamp: DateTime<Utc>,
+    pub object_hash: Option<String>,
+    /// The hash of the paste's content, unless it is private.
+    pub content_hash: Option<String>,
+    pub encoding: Option<String>,
+    pub key_id: Option<String>,
+    pub secret_hash: Option<String>,
+    pub tier: Option<String>,
+    pub token_name: Option<String>,
+    pub owner: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Paste {
+    /// The key of the paste's data in storage.
+    ///
+    /// Pastes created before deduplication are stored under their own key.
+    pub fn object_key(&self) -> &str {
+        self.object_hash.as_deref().unwrap_or(&self.key)
+    }
+
+    /// The encoding the paste's data is compressed with in storage.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.encoding.as_deref().and_then(Encoding::from_name)
+    }
+
+    /// Check a delete key against the paste's, in constant time.
+    pub fn verify_delete_key(&self, delete_key: &str) -> bool {
+        let Some(delete_key_hash) = &self.delete_key_hash else { return false };
+        let Some((salt, hash)) = delete_key_hash.split_once('$') else { return false };
+        let (Ok(salt), Ok(hash)) = (hex::decode(salt), hex::decode(hash)) else { return false };
+
+        salted_hash(&salt, delete_key).ct_eq(&hash).into()
+    }
+
+    /// The storage tier the paste's data is in.
+    ///
+    /// Pastes are stored in the hot tier until they are moved.
+    pub fn tier(&self) -> Tier {
+        self.tier
+            .as_deref()
+            .and_then(Tier::from_name)
+            .unwrap_or(Tier::Hot)
+    }
+}
+
+/// Hash a delete key with a random salt, for storing in place of the key.
+///
+/// The result holds the hex-encoded salt and hash separated by `$`.
+pub fn hash_delete_key(delete_key: &str) -> String {
+    let mut salt = [0u8; 16];
+    thread_rng().fill(&mut salt);
+
+    format!(
+        "{}${}",
+        hex::encode(salt),
+        hex::encode(salted_hash(&salt, delete_key))
+    )
+}
+
+fn salted_hash(salt: &[u8], delete_key: &str) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(salt)
+        .chain_update(delete_key)
+        .finalize()
+        .to_vec()
+}
+
+#[derive(FromRow)]
+pub struct ApiToken {
+    pub name: String,
+    pub owner: String,
+}
+
+/// The uploads made by a client within a window of time.
+#[derive(FromRow)]
+pub struct UploadUsage {
+    pub count: i64,
+    pub size: i64,
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+pub struct Object {
+    pub hash: String,
+    pub size: i64,
+    pub ref_count: i64,
+    pub encoding: Option<String>,
+    pub key_id: Option<String>,
+    pub tier: Option<String>,
 }
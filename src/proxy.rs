@@ -0,0 +1,279 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::rejection::ExtensionRejection;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+
+use crate::config::Config;
+use crate::App;
+
+/// The address of the client which made a request, looking past any trusted
+/// proxies it was forwarded through.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<Arc<App>> for ClientIp {
+    type Rejection = ExtensionRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &Arc<App>,
+    ) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer_addr) =
+            ConnectInfo::<SocketAddr>::from_request_parts(parts, app).await?;
+        Ok(ClientIp(client_ip(
+            &app.config,
+            peer_addr.ip(),
+            &parts.headers,
+        )))
+    }
+}
+
+/// The URL the service was reached at, without a trailing slash.
+pub struct BaseUrl(pub String);
+
+#[async_trait]
+impl FromRequestParts<Arc<App>> for BaseUrl {
+    type Rejection = ExtensionRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &Arc<App>,
+    ) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer_addr) =
+            ConnectInfo::<SocketAddr>::from_request_parts(parts, app).await?;
+        Ok(BaseUrl(base_url(
+            &app.config,
+            peer_addr.ip(),
+            &parts.headers,
+        )))
+    }
+}
+
+/// Get the address of the client which made a request.
+///
+/// Headers naming the client are only believed when the request came from a
+/// trusted proxy. `Forwarded` is preferred, then `X-Forwarded-For` and lastly
+/// `X-Real-IP`. The client is the last address forwarded through which is not
+/// itself a trusted proxy.
+pub fn client_ip(config: &Config, peer_ip: IpAddr, headers: &HeaderMap) -> IpAddr {
+    if !is_trusted_proxy(config, peer_ip) {
+        return peer_ip;
+    }
+
+    let hops = if headers.contains_key("forwarded") {
+        forwarded_hops(headers)
+    } else if headers.contains_key("x-forwarded-for") {
+        forwarded_for_hops(headers)
+    } else {
+        header_values(headers, "x-real-ip")
+            .map(parse_addr)
+            .collect()
+    };
+
+    let mut client_ip = peer_ip;
+    for addr in hops.into_iter().rev() {
+        // stop at anything which can't be trusted to have been forwarded
+        let Some(addr) = addr else { break };
+        client_ip = addr;
+        if !is_trusted_proxy(config, addr) {
+            break;
+        }
+    }
+    client_ip
+}
+
+/// Get the URL the service was reached at.
+///
+/// If enabled, this is taken from `X-Forwarded-Proto` and `X-Forwarded-Host`
+/// when the request came from a trusted proxy, falling back to the configured
+/// base URL.
+pub fn base_url(config: &Config, peer_ip: IpAddr, headers: &HeaderMap) -> String {
+    let base_url = config.base_url.trim_end_matches('/').to_owned();
+
+    if config.trust_forwarded_host != Some(true) || !is_trusted_proxy(config, peer_ip) {
+        return base_url;
+    }
+
+    // only the values set by the closest proxy can be trusted
+    let Some(host) = header_values(headers, "x-forwarded-host").last() else { return base_url };
+    let proto = header_values(headers, "x-forwarded-proto")
+        .last()
+        .unwrap_or("http");
+
+    format!("{proto}://{host}")
+}
+
+fn is_trusted_proxy(config: &Config, ip: IpAddr) -> bool {
+    config
+        .trusted_proxies
+        .iter()
+        .flatten()
+        .any(|net| net.contains(&ip))
+}
+
+/// Get the addresses in `Forwarded` headers, in the order they were added.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    header_values(headers, "forwarded")
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| parse_addr(node.trim().trim_matches('"')))
+        })
+        .collect()
+}
+
+/// Get the addresses in `X-Forwarded-For` headers, in the order they were
+/// added.
+fn forwarded_for_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    header_values(headers, "x-forwarded-for")
+        .map(parse_addr)
+        .collect()
+}
+
+/// Iterate over the comma separated values of every header with a name.
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+/// Parse an address which may have a port, and IPv6 addresses in brackets.
+fn parse_addr(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            addr.strip_prefix('[')
+                .and_then(|addr| addr.strip_suffix(']'))
+                .and_then(|addr| addr.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    const PEER: &str = "10.0.0.1";
+
+    fn test_config() -> Config {
+        let mut config: Config = toml::from_str(include_str!("../config.toml.example")).unwrap();
+        config.trusted_proxies = Some(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "fd00::/8".parse().unwrap(),
+        ]);
+        config
+    }
+
+    fn header_map(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    fn client_ip_of(peer: &str, headers: &HeaderMap) -> IpAddr {
+        client_ip(&test_config(), peer.parse().unwrap(), headers)
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let forged = header_map(&[
+            ("x-forwarded-for", "1.2.3.4"),
+            ("forwarded", "for=1.2.3.4"),
+            ("x-real-ip", "1.2.3.4"),
+        ]);
+        assert_eq!(client_ip_of("5.6.7.8", &forged), ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn no_headers() {
+        assert_eq!(client_ip_of(PEER, &HeaderMap::new()), ip(PEER));
+    }
+
+    #[test]
+    fn forwarded_for_walks_past_trusted_proxies() {
+        // the client can put anything at the start, but only the address
+        // added by the first trusted proxy counts
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4, 5.6.7.8, 10.0.0.2")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn forwarded_for_across_headers() {
+        let headers = header_map(&[
+            ("x-forwarded-for", "1.2.3.4"),
+            ("x-forwarded-for", "5.6.7.8, 10.0.0.2"),
+        ]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn forwarded_for_only_trusted_proxies() {
+        let headers = header_map(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("10.0.0.3"));
+    }
+
+    #[test]
+    fn unparseable_hop_ends_walk() {
+        let headers = header_map(&[("x-forwarded-for", "1.2.3.4, garbage, 10.0.0.2")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("10.0.0.2"));
+
+        let headers = header_map(&[("forwarded", "for=1.2.3.4, for=unknown")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip(PEER));
+    }
+
+    #[test]
+    fn forwarded_preferred() {
+        let headers = header_map(&[
+            ("forwarded", "for=5.6.7.8;proto=https"),
+            ("x-forwarded-for", "1.2.3.4"),
+            ("x-real-ip", "1.2.3.4"),
+        ]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn forwarded_addresses() {
+        let headers = header_map(&[("forwarded", "for=\"[2001:db8::1]:4711\"")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("2001:db8::1"));
+
+        let headers = header_map(&[("forwarded", "proto=http; For=\"5.6.7.8:80\"")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("5.6.7.8"));
+
+        let headers = header_map(&[("forwarded", "for=\"[2001:db8::1]\", for=\"[fd00::1]\"")]);
+        assert_eq!(client_ip_of("fd00::2", &headers), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn real_ip() {
+        let headers = header_map(&[("x-real-ip", "5.6.7.8")]);
+        assert_eq!(client_ip_of(PEER, &headers), ip("5.6.7.8"));
+    }
+
+    #[test]
+    fn parse_addrs() {
+        assert_eq!(parse_addr("1.2.3.4"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_addr("1.2.3.4:80"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_addr("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_addr("[2001:db8::1]"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_addr("[2001:db8::1]:80"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_addr("unknown"), None);
+        assert_eq!(parse_addr("_hidden"), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::config;
+use crate::error::AppError;
+
+/// The number of clients tracked before idle clients are forgotten.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// The rate limiters for each group of routes.
+#[derive(Clone, Default)]
+pub struct RateLimiters {
+    pub upload: Option<RateLimiter>,
+    pub download: Option<RateLimiter>,
+    pub failed_delete: Option<RateLimiter>,
+}
+
+impl RateLimiters {
+    pub fn new(config: Option<&config::RateLimits>) -> Self {
+        let Some(config) = config else { return Self::default() };
+
+        RateLimiters {
+            upload: config.upload.as_ref().map(RateLimiter::new),
+            download: config.download.as_ref().map(RateLimiter::new),
+            failed_delete: config.failed_delete.as_ref().map(RateLimiter::new),
+        }
+    }
+}
+
+/// A token bucket rate limiter, keeping a bucket for each client IP.
+///
+/// Each bucket holds up to a burst of tokens and is refilled at a steady rate,
+/// so clients can make a few requests at once but no more than the rate over
+/// time.
+#[derive(Clone)]
+pub struct RateLimiter {
+    burst: f64,
+    per_sec: f64,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &config::RateLimit) -> Self {
+        RateLimiter {
+            burst: config.burst as f64,
+            per_sec: config.per_minute as f64 / 60.0,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Take a token for a client, failing if there are none left.
+    pub fn acquire(&self, client_ip: IpAddr) -> crate::AppResult<()> {
+        self.update(client_ip, true)
+    }
+
+    /// Check that a client has a token left without taking it.
+    pub fn check(&self, client_ip: IpAddr) -> crate::AppResult<()> {
+        self.update(client_ip, false)
+    }
+
+    /// Take a token for a client if there is one left.
+    pub fn consume(&self, client_ip: IpAddr) {
+        let _ = self.acquire(client_ip);
+    }
+
+    fn update(&self, client_ip: IpAddr, take: bool) -> crate::AppResult<()> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= PRUNE_THRESHOLD && !buckets.contains_key(&client_ip) {
+            // full buckets are no different from new ones
+            buckets.retain(|_, bucket| self.tokens(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(client_ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.tokens(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            let retry_after_secs = ((1.0 - bucket.tokens) / self.per_sec).ceil().max(1.0);
+            return Err(AppError::RateLimited {
+                retry_after_secs: retry_after_secs as u64,
+            });
+        }
+
+        if take {
+            bucket.tokens -= 1.0;
+        }
+
+        Ok(())
+    }
+
+    /// Get the tokens in a bucket after refilling it.
+    fn tokens(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_sec).min(self.burst)
+    }
+}
@@ -0,0 +1,110 @@
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+
+use crate::compression::Encoding;
+use crate::error::AppError;
+use crate::{config, AppResult};
+
+use super::file::FileStorage;
+#[cfg(feature = "s3")]
+use super::s3::S3Storage;
+use super::Storage;
+
+/// A single storage backend which can be combined with others.
+#[derive(Clone)]
+pub enum Backend {
+    File(FileStorage),
+    #[cfg(feature = "s3")]
+    S3(S3Storage),
+}
+
+impl Backend {
+    pub async fn new(config: &config::Backend) -> anyhow::Result<Self> {
+        let backend = match config {
+            config::Backend::File(file_config) => {
+                Backend::File(FileStorage::new(&file_config.dir).await?)
+            }
+            #[cfg(feature = "s3")]
+            config::Backend::S3(s3_config) => Backend::S3(S3Storage::new(s3_config).await),
+        };
+        Ok(backend)
+    }
+}
+
+impl Storage for Backend {
+    async fn get_object(
+        &self,
+        key: &str,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        match self {
+            Backend::File(fs) => fs.get_object(key).await.map(StreamExt::boxed),
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.get_object(key).await.map(StreamExt::boxed),
+        }
+    }
+
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<AppError>,
+    {
+        match self {
+            Backend::File(fs) => fs.put_object(key, data).await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.put_object(key, data).await,
+        }
+    }
+
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        match self {
+            Backend::File(fs) => fs.list_objects().await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.list_objects().await,
+        }
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        match self {
+            Backend::File(fs) => fs.get_object_size(key).await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.get_object_size(key).await,
+        }
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
+        match self {
+            Backend::File(fs) => fs.delete_object(key).await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.delete_object(key).await,
+        }
+    }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        match self {
+            Backend::File(fs) => fs.rename_object(from, to).await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.rename_object(from, to).await,
+        }
+    }
+
+    async fn get_object_url(
+        &self,
+        key: &str,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        match self {
+            Backend::File(fs) => fs.get_object_url(key, file_name, encoding).await,
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.get_object_url(key, file_name, encoding).await,
+        }
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        match self {
+            Backend::File(fs) => fs.available_space(),
+            #[cfg(feature = "s3")]
+            Backend::S3(s3) => s3.available_space(),
+        }
+    }
+}
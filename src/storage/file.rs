@@ -35,7 +35,7 @@ impl FileStorage {
 
 impl Storage for FileStorage {
     async fn get_object(
-        &mut self,
+        &self,
         key: &str,
     ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
         assert!(!key.contains('/'));
@@ -48,7 +48,7 @@ impl Storage for FileStorage {
         Ok(framed_read)
     }
 
-    async fn put_object<S, E>(&mut self, key: &str, mut data: S) -> crate::AppResult<usize>
+    async fn put_object<S, E>(&self, key: &str, mut data: S) -> crate::AppResult<usize>
     where
         S: Stream<Item = Result<Bytes, E>> + Unpin,
         E: Into<AppError>,
@@ -67,11 +67,45 @@ impl Storage for FileStorage {
         Ok(size)
     }
 
-    async fn delete_object(&mut self, key: &str) -> crate::AppResult<()> {
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        let mut keys = Vec::new();
+
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() {
+                keys.extend(entry.file_name().into_string());
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        assert!(!key.contains('/'));
+
+        let metadata = fs::metadata(self.dir.join(key)).await?;
+
+        Ok(metadata.len() as usize)
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
         assert!(!key.contains('/'));
 
         fs::remove_file(self.dir.join(key)).await?;
 
         Ok(())
     }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        assert!(!from.contains('/'));
+        assert!(!to.contains('/'));
+
+        fs::rename(self.dir.join(from), self.dir.join(to)).await?;
+
+        Ok(())
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        Ok(Some(fs2::available_space(&self.dir)?))
+    }
 }
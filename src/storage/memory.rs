@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream, Stream, TryStreamExt};
+
+use crate::error::AppError;
+use crate::AppResult;
+
+use super::Storage;
+
+/// Storage kept entirely in process memory, lost when the service exits.
+#[derive(Clone)]
+pub struct MemoryStorage {
+    inner: Arc<Mutex<Inner>>,
+    max_size: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    objects: HashMap<String, Bytes>,
+    size: usize,
+}
+
+impl MemoryStorage {
+    pub fn new(max_size: usize) -> Self {
+        MemoryStorage {
+            inner: Default::default(),
+            max_size,
+        }
+    }
+
+    fn used_size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn get_object(
+        &self,
+        key: &str,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        let data = self
+            .inner
+            .lock()
+            .unwrap()
+            .objects
+            .get(key)
+            .cloned()
+            .ok_or(AppError::NotFound)?;
+
+        Ok(stream::once(async { Ok(data) }))
+    }
+
+    async fn put_object<S, E>(&self, key: &str, mut data: S) -> crate::AppResult<usize>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<AppError>,
+    {
+        let mut buffer = BytesMut::new();
+        while let Some(chunk) = data.try_next().await.map_err(Into::into)? {
+            // fail early rather than buffering an upload that will never fit
+            if self.used_size() + buffer.len() + chunk.len() > self.max_size {
+                return Err(AppError::InsufficientStorage);
+            }
+            buffer.extend_from_slice(&chunk);
+        }
+        let size = buffer.len();
+
+        let mut inner = self.inner.lock().unwrap();
+        let replaced_size = inner.objects.get(key).map_or(0, Bytes::len);
+        if inner.size - replaced_size + size > self.max_size {
+            return Err(AppError::InsufficientStorage);
+        }
+        inner.objects.insert(key.to_owned(), buffer.freeze());
+        inner.size = inner.size - replaced_size + size;
+
+        Ok(size)
+    }
+
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        Ok(self.inner.lock().unwrap().objects.keys().cloned().collect())
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        let inner = self.inner.lock().unwrap();
+        let data = inner.objects.get(key).ok_or(AppError::NotFound)?;
+
+        Ok(data.len())
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let data = inner.objects.remove(key).ok_or(AppError::NotFound)?;
+        inner.size -= data.len();
+
+        Ok(())
+    }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let data = inner.objects.remove(from).ok_or(AppError::NotFound)?;
+        if let Some(replaced) = inner.objects.insert(to.to_owned(), data) {
+            inner.size -= replaced.len();
+        }
+
+        Ok(())
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        Ok(Some((self.max_size - self.used_size()) as u64))
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::Stream;
+use tracing::{info, warn};
+
+use crate::compression::Encoding;
+use crate::error::AppError;
+use crate::{config, AppResult};
+
+use super::backend::Backend;
+use super::Storage;
+
+/// How often failed replica writes are retried by default.
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Storage which keeps copies of every object on a primary backend and any
+/// number of replicas.
+///
+/// Writes go to the primary first and are then copied to each replica. Replica
+/// writes which fail are queued and retried in the background, but the queue is
+/// not persisted, so replicas can fall behind if the service is restarted.
+#[derive(Clone)]
+pub struct MirrorStorage {
+    backends: Vec<Backend>,
+    pending: Arc<Mutex<Vec<PendingSync>>>,
+}
+
+/// An object which needs to be brought in line with the primary on a replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingSync {
+    replica: usize,
+    key: String,
+}
+
+impl MirrorStorage {
+    pub async fn new(config: &config::MirrorStorage) -> anyhow::Result<Self> {
+        let mut backends = vec![Backend::new(&config.primary).await?];
+        for replica_config in &config.replicas {
+            backends.push(Backend::new(replica_config).await?);
+        }
+
+        let storage = MirrorStorage {
+            backends,
+            pending: Default::default(),
+        };
+
+        let retry_interval = config
+            .retry_interval_secs
+            .map_or(DEFAULT_RETRY_INTERVAL, Duration::from_secs);
+        tokio::spawn(storage.clone().retry_pending(retry_interval));
+
+        Ok(storage)
+    }
+
+    /// Periodically retry replica writes which failed.
+    async fn retry_pending(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+
+            let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+            if pending.is_empty() {
+                continue;
+            }
+
+            info!("retrying {} failed replica writes", pending.len());
+            for PendingSync { replica, key } in pending {
+                self.sync_replica(replica, &key).await;
+            }
+        }
+    }
+
+    /// Make a replica's copy of an object match the primary, queuing it to be
+    /// retried if that fails.
+    async fn sync_replica(&self, replica: usize, key: &str) {
+        let primary = &self.backends[0];
+        let backend = &self.backends[replica];
+
+        let result = match primary.get_object(key).await {
+            Ok(data) => backend.put_object(key, Box::pin(data)).await.map(|_| ()),
+            Err(AppError::NotFound) => match backend.delete_object(key).await {
+                Err(AppError::NotFound) => Ok(()),
+                result => result,
+            },
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            warn!("failed to write '{key}' to replica {replica}: {e}");
+
+            let sync = PendingSync {
+                replica,
+                key: key.to_owned(),
+            };
+            let mut pending = self.pending.lock().unwrap();
+            if !pending.contains(&sync) {
+                pending.push(sync);
+            }
+        }
+    }
+
+    /// Bring every replica's copy of an object in line with the primary.
+    async fn sync_replicas(&self, key: &str) {
+        for replica in 1..self.backends.len() {
+            self.sync_replica(replica, key).await;
+        }
+    }
+
+    /// Find the first backend which can serve an object.
+    async fn find_object(&self, key: &str) -> crate::AppResult<usize> {
+        let mut last_error = None;
+        for (index, backend) in self.backends.iter().enumerate() {
+            match backend.get_object_size(key).await {
+                Ok(_) => return Ok(index),
+                Err(e) => {
+                    if index == 0 {
+                        warn!("failed to find '{key}' on primary: {e}");
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
+    fn primary(&self) -> &Backend {
+        &self.backends[0]
+    }
+}
+
+impl Storage for MirrorStorage {
+    async fn get_object(
+        &self,
+        key: &str,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        let index = self.find_object(key).await?;
+        self.backends[index].get_object(key).await
+    }
+
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<AppError>,
+    {
+        let size = self.primary().put_object(key, data).await?;
+        self.sync_replicas(key).await;
+        Ok(size)
+    }
+
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        self.primary().list_objects().await
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        let index = self.find_object(key).await?;
+        self.backends[index].get_object_size(key).await
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
+        self.primary().delete_object(key).await?;
+        self.sync_replicas(key).await;
+        Ok(())
+    }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        self.primary().rename_object(from, to).await?;
+
+        for replica in 1..self.backends.len() {
+            if let Err(e) = self.backends[replica].rename_object(from, to).await {
+                warn!("failed to rename '{from}' to '{to}' on replica {replica}: {e}");
+                self.sync_replica(replica, from).await;
+                self.sync_replica(replica, to).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_object_url(
+        &self,
+        key: &str,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        self.primary()
+            .get_object_url(key, file_name, encoding)
+            .await
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        self.backends[0].available_space()
+    }
+}
@@ -1,69 +1,256 @@
+use anyhow::Context;
 use axum::body::Bytes;
-use futures_util::{Stream, StreamExt};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
 
+use crate::compression::Encoding;
+use crate::config::{self, StorageKind};
 use crate::error::AppError;
 use crate::AppResult;
 
+pub mod backend;
 pub mod file;
+pub mod memory;
+pub mod mirror;
+pub mod tiered;
 
 #[cfg(feature = "s3")]
 pub mod s3;
 
 pub trait Storage {
     /// Get an object by key.
-    async fn get_object(
-        &mut self,
-        key: &str,
-    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>>;
+    async fn get_object(&self, key: &str)
+        -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>>;
 
     /// Put an object's data by key.
-    async fn put_object<S, E>(&mut self, key: &str, data: S) -> crate::AppResult<usize>
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
     where
         S: Stream<Item = Result<Bytes, E>> + Unpin,
         E: Into<AppError>;
 
+    /// List the keys of all stored objects.
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>>;
+
+    /// Get the size of an object by key.
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize>;
+
     /// Delete an object by key.
-    async fn delete_object(&mut self, key: &str) -> crate::AppResult<()>;
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()>;
+
+    /// Move an object to a new key, replacing any object already there.
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()>;
+
+    /// Get a short-lived URL from which an object can be downloaded directly,
+    /// if the backend supports it.
+    async fn get_object_url(
+        &self,
+        _key: &str,
+        _file_name: &str,
+        _encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        Ok(None)
+    }
+
+    /// Get the space left for new objects in bytes, if the backend is limited.
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        Ok(None)
+    }
 }
 
 #[derive(Clone)]
 pub enum AnyStorage {
     File(file::FileStorage),
+    Memory(memory::MemoryStorage),
+    Mirror(mirror::MirrorStorage),
+    Tiered(tiered::TieredStorage),
     #[cfg(feature = "s3")]
     S3(s3::S3Storage),
 }
 
+impl AnyStorage {
+    /// Set up a storage backend of the given kind from its configuration.
+    pub async fn from_config(config: &config::Storage, kind: StorageKind) -> anyhow::Result<Self> {
+        let storage = match kind {
+            StorageKind::File => file::FileStorage::new(&config.file.dir).await?.into(),
+            StorageKind::Memory => {
+                let memory_config = config
+                    .memory
+                    .as_ref()
+                    .context("missing memory storage config")?;
+                memory::MemoryStorage::new(memory_config.max_size).into()
+            }
+            StorageKind::Mirror => {
+                let mirror_config = config
+                    .mirror
+                    .as_ref()
+                    .context("missing mirror storage config")?;
+                mirror::MirrorStorage::new(mirror_config).await?.into()
+            }
+            StorageKind::Tiered => {
+                let tiered_config = config
+                    .tiered
+                    .as_ref()
+                    .context("missing tiered storage config")?;
+                tiered::TieredStorage::new(tiered_config).await?.into()
+            }
+            #[cfg(feature = "s3")]
+            StorageKind::S3 => s3::S3Storage::new(&config.s3).await.into(),
+        };
+        Ok(storage)
+    }
+
+    /// Get an object by key from the given tier, which is ignored unless the
+    /// storage is tiered.
+    pub async fn get_object_in(
+        &self,
+        key: &str,
+        tier: tiered::Tier,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        match self {
+            AnyStorage::Tiered(tiered) => {
+                tiered.get_object_in(key, tier).await.map(StreamExt::boxed)
+            }
+            storage => storage.get_object(key).await.map(StreamExt::boxed),
+        }
+    }
+
+    /// Get a URL for an object from the given tier, which is ignored unless
+    /// the storage is tiered.
+    pub async fn get_object_url_in(
+        &self,
+        key: &str,
+        tier: tiered::Tier,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        match self {
+            AnyStorage::Tiered(tiered) => {
+                tiered
+                    .get_object_url_in(key, tier, file_name, encoding)
+                    .await
+            }
+            storage => storage.get_object_url(key, file_name, encoding).await,
+        }
+    }
+}
+
 impl Storage for AnyStorage {
     async fn get_object(
-        &mut self,
+        &self,
         key: &str,
     ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
         match self {
             AnyStorage::File(fs) => fs.get_object(key).await.map(StreamExt::boxed),
+            AnyStorage::Memory(memory) => memory.get_object(key).await.map(StreamExt::boxed),
+            AnyStorage::Mirror(mirror) => mirror.get_object(key).await.map(StreamExt::boxed),
+            AnyStorage::Tiered(tiered) => tiered.get_object(key).await.map(StreamExt::boxed),
             #[cfg(feature = "s3")]
             AnyStorage::S3(s3) => s3.get_object(key).await.map(StreamExt::boxed),
         }
     }
 
-    async fn put_object<S, E>(&mut self, key: &str, data: S) -> crate::AppResult<usize>
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
     where
         S: Stream<Item = Result<Bytes, E>> + Unpin,
         E: Into<AppError>,
     {
         match self {
             AnyStorage::File(fs) => fs.put_object(key, data).await,
+            AnyStorage::Memory(memory) => memory.put_object(key, data).await,
+            AnyStorage::Mirror(mirror) => mirror.put_object(key, data).await,
+            AnyStorage::Tiered(tiered) => tiered.put_object(key, data).await,
             #[cfg(feature = "s3")]
             AnyStorage::S3(s3) => s3.put_object(key, data).await,
         }
     }
 
-    async fn delete_object(&mut self, key: &str) -> crate::AppResult<()> {
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        match self {
+            AnyStorage::File(fs) => fs.list_objects().await,
+            AnyStorage::Memory(memory) => memory.list_objects().await,
+            AnyStorage::Mirror(mirror) => mirror.list_objects().await,
+            AnyStorage::Tiered(tiered) => tiered.list_objects().await,
+            #[cfg(feature = "s3")]
+            AnyStorage::S3(s3) => s3.list_objects().await,
+        }
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        match self {
+            AnyStorage::File(fs) => fs.get_object_size(key).await,
+            AnyStorage::Memory(memory) => memory.get_object_size(key).await,
+            AnyStorage::Mirror(mirror) => mirror.get_object_size(key).await,
+            AnyStorage::Tiered(tiered) => tiered.get_object_size(key).await,
+            #[cfg(feature = "s3")]
+            AnyStorage::S3(s3) => s3.get_object_size(key).await,
+        }
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
         match self {
             AnyStorage::File(fs) => fs.delete_object(key).await,
+            AnyStorage::Memory(memory) => memory.delete_object(key).await,
+            AnyStorage::Mirror(mirror) => mirror.delete_object(key).await,
+            AnyStorage::Tiered(tiered) => tiered.delete_object(key).await,
             #[cfg(feature = "s3")]
             AnyStorage::S3(s3) => s3.delete_object(key).await,
         }
     }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        match self {
+            AnyStorage::File(fs) => fs.rename_object(from, to).await,
+            AnyStorage::Memory(memory) => memory.rename_object(from, to).await,
+            AnyStorage::Mirror(mirror) => mirror.rename_object(from, to).await,
+            AnyStorage::Tiered(tiered) => tiered.rename_object(from, to).await,
+            #[cfg(feature = "s3")]
+            AnyStorage::S3(s3) => s3.rename_object(from, to).await,
+        }
+    }
+
+    async fn get_object_url(
+        &self,
+        key: &str,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        match self {
+            AnyStorage::File(fs) => fs.get_object_url(key, file_name, encoding).await,
+            AnyStorage::Memory(memory) => memory.get_object_url(key, file_name, encoding).await,
+            AnyStorage::Mirror(mirror) => mirror.get_object_url(key, file_name, encoding).await,
+            AnyStorage::Tiered(tiered) => tiered.get_object_url(key, file_name, encoding).await,
+            #[cfg(feature = "s3")]
+            AnyStorage::S3(s3) => s3.get_object_url(key, file_name, encoding).await,
+        }
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        match self {
+            AnyStorage::File(fs) => fs.available_space(),
+            AnyStorage::Memory(memory) => memory.available_space(),
+            AnyStorage::Mirror(mirror) => mirror.available_space(),
+            AnyStorage::Tiered(tiered) => tiered.available_space(),
+            #[cfg(feature = "s3")]
+            AnyStorage::S3(s3) => s3.available_space(),
+        }
+    }
+}
+
+/// Read a stream of data to the end, returning its size and hash.
+pub async fn hash_data<S>(data: S) -> crate::AppResult<(usize, String)>
+where
+    S: Stream<Item = AppResult<Bytes>>,
+{
+    let mut data = Box::pin(data);
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = data.try_next().await? {
+        hasher.update(&chunk);
+        size += chunk.len();
+    }
+
+    Ok((size, hex::encode(hasher.finalize())))
 }
 
 impl From<file::FileStorage> for AnyStorage {
@@ -72,6 +259,24 @@ impl From<file::FileStorage> for AnyStorage {
     }
 }
 
+impl From<memory::MemoryStorage> for AnyStorage {
+    fn from(value: memory::MemoryStorage) -> Self {
+        AnyStorage::Memory(value)
+    }
+}
+
+impl From<mirror::MirrorStorage> for AnyStorage {
+    fn from(value: mirror::MirrorStorage) -> Self {
+        AnyStorage::Mirror(value)
+    }
+}
+
+impl From<tiered::TieredStorage> for AnyStorage {
+    fn from(value: tiered::TieredStorage) -> Self {
+        AnyStorage::Tiered(value)
+    }
+}
+
 #[cfg(feature = "s3")]
 impl From<s3::S3Storage> for AnyStorage {
     fn from(value: s3::S3Storage) -> Self {
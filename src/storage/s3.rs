@@ -1,11 +1,18 @@
+use std::time::Duration;
+
 use aws_config::retry::RetryConfig;
+use aws_config::timeout::TimeoutConfig;
 use aws_sdk_s3 as s3;
 
 use bytes::{Bytes, BytesMut};
 use futures_util::{Stream, TryStreamExt};
+use s3::model::StorageClass;
+use s3::presigning::config::PresigningConfig;
+use urlencoding::encode;
 
+use crate::compression::Encoding;
 use crate::error::AppError;
-use crate::AppResult;
+use crate::{config, AppResult};
 
 use super::Storage;
 
@@ -13,40 +20,95 @@ use super::Storage;
 pub struct S3Storage {
     client: s3::Client,
     bucket: String,
+    key_prefix: String,
+    storage_class: Option<StorageClass>,
+    presign_expiration: Option<Duration>,
 }
 
 impl S3Storage {
-    pub async fn new(bucket: &str, region: Option<&str>, endpoint: Option<&str>) -> Self {
+    pub async fn new(config: &config::S3Storage) -> Self {
         let client = {
-            let mut config_loader = aws_config::from_env().retry_config(RetryConfig::disabled());
-            if let Some(region) = region {
+            let retry_config = match config.max_attempts {
+                Some(max_attempts) => {
+                    let mut retry_config = RetryConfig::standard().with_max_attempts(max_attempts);
+                    if let Some(initial_backoff_ms) = config.initial_backoff_ms {
+                        retry_config = retry_config
+                            .with_initial_backoff(Duration::from_millis(initial_backoff_ms));
+                    }
+                    retry_config
+                }
+                None => RetryConfig::disabled(),
+            };
+
+            let mut timeout_config = TimeoutConfig::builder();
+            if let Some(connect_timeout_secs) = config.connect_timeout_secs {
+                timeout_config =
+                    timeout_config.connect_timeout(Duration::from_secs(connect_timeout_secs));
+            }
+            if let Some(read_timeout_secs) = config.read_timeout_secs {
+                timeout_config =
+                    timeout_config.read_timeout(Duration::from_secs(read_timeout_secs));
+            }
+            if let Some(operation_timeout_secs) = config.operation_timeout_secs {
+                timeout_config =
+                    timeout_config.operation_timeout(Duration::from_secs(operation_timeout_secs));
+            }
+
+            let mut config_loader = aws_config::from_env()
+                .retry_config(retry_config)
+                .timeout_config(timeout_config.build());
+            if let Some(region) = &config.region {
                 config_loader = config_loader.region(s3::Region::new(region.to_owned()));
             }
-            if let Some(endpoint) = endpoint {
+            if let Some(endpoint) = &config.endpoint {
                 config_loader = config_loader.endpoint_url(endpoint);
             }
+            if let Some(profile) = &config.profile {
+                config_loader = config_loader.profile_name(profile);
+            }
+            if let Some(credentials) = &config.credentials {
+                config_loader = config_loader.credentials_provider(s3::Credentials::new(
+                    &credentials.access_key_id,
+                    &credentials.secret_access_key,
+                    credentials.session_token.clone(),
+                    None,
+                    "nonbin",
+                ));
+            }
             let sdk_config = config_loader.load().await;
 
-            s3::Client::new(&sdk_config)
+            let s3_config = s3::config::Builder::from(&sdk_config)
+                .force_path_style(config.force_path_style.unwrap_or(false))
+                .build();
+
+            s3::Client::from_conf(s3_config)
         };
 
         S3Storage {
             client,
-            bucket: bucket.into(),
+            bucket: config.bucket.clone(),
+            key_prefix: config.key_prefix.clone().unwrap_or_default(),
+            storage_class: config.storage_class.as_deref().map(StorageClass::from),
+            presign_expiration: config.presign_expiration_secs.map(Duration::from_secs),
         }
     }
+
+    /// Get the full key of an object in the bucket.
+    fn object_key(&self, key: &str) -> String {
+        format!("{prefix}{key}", prefix = self.key_prefix)
+    }
 }
 
 impl Storage for S3Storage {
     async fn get_object(
-        &mut self,
+        &self,
         key: &str,
     ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
         let object = self
             .client
             .get_object()
             .bucket(&self.bucket)
-            .key(key)
+            .key(self.object_key(key))
             .send()
             .await?;
         Ok(object.body.map_err(|e| AppError::S3 {
@@ -54,7 +116,7 @@ impl Storage for S3Storage {
         }))
     }
 
-    async fn put_object<S, E>(&mut self, key: &str, data: S) -> crate::AppResult<usize>
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
     where
         S: Stream<Item = Result<Bytes, E>> + Unpin,
         E: Into<AppError>,
@@ -69,20 +131,111 @@ impl Storage for S3Storage {
         self.client
             .put_object()
             .bucket(&self.bucket)
-            .key(key)
+            .key(self.object_key(key))
             .body(body.into())
+            .set_storage_class(self.storage_class.clone())
             .send()
             .await?;
         Ok(size)
     }
 
-    async fn delete_object(&mut self, key: &str) -> crate::AppResult<()> {
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        let mut keys = Vec::new();
+
+        let mut continuation_token = None;
+        loop {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(&self.key_prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+
+            let objects = output.contents().unwrap_or_default();
+            keys.extend(objects.iter().filter_map(|object| {
+                let key = object.key()?.strip_prefix(&self.key_prefix)?;
+                Some(key.to_owned())
+            }));
+
+            match output.next_continuation_token() {
+                Some(token) => continuation_token = Some(token.to_owned()),
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        let object = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(object.content_length() as usize)
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
         self.client
             .delete_object()
             .bucket(&self.bucket)
-            .key(key)
+            .key(self.object_key(key))
             .send()
             .await?;
         Ok(())
     }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        let copy_source = format!(
+            "{bucket}/{key}",
+            bucket = self.bucket,
+            key = encode(&self.object_key(from))
+        );
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(copy_source)
+            .key(self.object_key(to))
+            .set_storage_class(self.storage_class.clone())
+            .send()
+            .await?;
+        self.delete_object(from).await
+    }
+
+    async fn get_object_url(
+        &self,
+        key: &str,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        let Some(expiration) = self.presign_expiration else { return Ok(None) };
+
+        let presigning_config =
+            PresigningConfig::expires_in(expiration).map_err(|e| AppError::S3 {
+                source: Box::new(e),
+            })?;
+
+        let content_type = mime_guess::from_path(file_name).first_or_octet_stream();
+        let content_disposition = format!(
+            "inline; filename*=UTF-8''{encoded_file_name}",
+            encoded_file_name = encode(file_name)
+        );
+
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .response_content_type(content_type.essence_str())
+            .response_content_disposition(content_disposition)
+            .set_response_content_encoding(encoding.map(|encoding| encoding.name().to_owned()))
+            .presigned(presigning_config)
+            .await?;
+
+        Ok(Some(request.uri().to_string()))
+    }
 }
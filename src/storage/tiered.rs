@@ -0,0 +1,192 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use futures_util::Stream;
+
+use crate::compression::Encoding;
+use crate::error::AppError;
+use crate::{config, AppResult};
+
+use super::backend::Backend;
+use super::file::FileStorage;
+use super::Storage;
+
+/// The tier an object is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Hot,
+    Cold,
+}
+
+impl Tier {
+    /// The name of the tier as stored in the database.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Hot => "hot",
+            Tier::Cold => "cold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hot" => Some(Tier::Hot),
+            "cold" => Some(Tier::Cold),
+            _ => None,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Tier::Hot => Tier::Cold,
+            Tier::Cold => Tier::Hot,
+        }
+    }
+}
+
+/// Storage which keeps new objects on local disk and moves them to a cold
+/// backend once they are old or the disk fills up.
+///
+/// New objects are always written to the hot tier. Moving objects between
+/// tiers is left to the caller, which keeps track of where each object is.
+#[derive(Clone)]
+pub struct TieredStorage {
+    hot: Backend,
+    hot_dir: PathBuf,
+    cold: Backend,
+}
+
+impl TieredStorage {
+    pub async fn new(config: &config::TieredStorage) -> anyhow::Result<Self> {
+        Ok(TieredStorage {
+            hot: Backend::File(FileStorage::new(&config.hot.dir).await?),
+            hot_dir: config.hot.dir.clone(),
+            cold: Backend::new(&config.cold).await?,
+        })
+    }
+
+    /// Get the backend of a tier.
+    pub fn backend(&self, tier: Tier) -> &Backend {
+        match tier {
+            Tier::Hot => &self.hot,
+            Tier::Cold => &self.cold,
+        }
+    }
+
+    /// Get an object by key from the tier it is stored in.
+    ///
+    /// The other tier is tried as well if the object is missing, since it may
+    /// have been moved after its tier was looked up.
+    pub async fn get_object_in(
+        &self,
+        key: &str,
+        tier: Tier,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        let tier = match self.backend(tier).get_object_size(key).await {
+            Err(AppError::NotFound) => tier.other(),
+            _ => tier,
+        };
+        self.backend(tier).get_object(key).await
+    }
+
+    /// Get a URL for an object from the tier it is stored in, if its backend
+    /// supports it.
+    pub async fn get_object_url_in(
+        &self,
+        key: &str,
+        tier: Tier,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        self.backend(tier)
+            .get_object_url(key, file_name, encoding)
+            .await
+    }
+
+    /// Copy an object from the hot tier to the cold tier.
+    pub async fn copy_to_cold(&self, key: &str) -> crate::AppResult<()> {
+        let data = Box::pin(self.hot.get_object(key).await?);
+        self.cold.put_object(key, data).await?;
+        Ok(())
+    }
+
+    /// Get the fraction of the hot tier's disk which is in use.
+    pub fn disk_usage(&self) -> crate::AppResult<f64> {
+        let total = fs2::total_space(&self.hot_dir)?;
+        let available = fs2::available_space(&self.hot_dir)?;
+        Ok(1.0 - available as f64 / total as f64)
+    }
+
+    /// Find the tier an object is stored in, preferring the hot tier.
+    async fn find_tier(&self, key: &str) -> crate::AppResult<Tier> {
+        match self.hot.get_object_size(key).await {
+            Ok(_) => Ok(Tier::Hot),
+            Err(AppError::NotFound) => {
+                self.cold.get_object_size(key).await?;
+                Ok(Tier::Cold)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Storage for TieredStorage {
+    async fn get_object(
+        &self,
+        key: &str,
+    ) -> crate::AppResult<impl Stream<Item = AppResult<Bytes>>> {
+        let tier = self.find_tier(key).await?;
+        self.backend(tier).get_object(key).await
+    }
+
+    async fn put_object<S, E>(&self, key: &str, data: S) -> crate::AppResult<usize>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<AppError>,
+    {
+        self.hot.put_object(key, data).await
+    }
+
+    async fn list_objects(&self) -> crate::AppResult<Vec<String>> {
+        let mut keys = BTreeSet::new();
+        keys.extend(self.hot.list_objects().await?);
+        keys.extend(self.cold.list_objects().await?);
+        Ok(keys.into_iter().collect())
+    }
+
+    async fn get_object_size(&self, key: &str) -> crate::AppResult<usize> {
+        let tier = self.find_tier(key).await?;
+        self.backend(tier).get_object_size(key).await
+    }
+
+    async fn delete_object(&self, key: &str) -> crate::AppResult<()> {
+        // an object can be in both tiers if it was being moved
+        let hot = self.hot.delete_object(key).await;
+        let cold = self.cold.delete_object(key).await;
+        match (hot, cold) {
+            (Err(AppError::NotFound), result) | (result, Err(AppError::NotFound)) => result,
+            (hot, cold) => hot.and(cold),
+        }
+    }
+
+    async fn rename_object(&self, from: &str, to: &str) -> crate::AppResult<()> {
+        let tier = self.find_tier(from).await?;
+        self.backend(tier).rename_object(from, to).await
+    }
+
+    async fn get_object_url(
+        &self,
+        key: &str,
+        file_name: &str,
+        encoding: Option<Encoding>,
+    ) -> crate::AppResult<Option<String>> {
+        let tier = self.find_tier(key).await?;
+        self.backend(tier)
+            .get_object_url(key, file_name, encoding)
+            .await
+    }
+
+    fn available_space(&self) -> crate::AppResult<Option<u64>> {
+        self.hot.available_space()
+    }
+}